@@ -1,19 +1,52 @@
 use std::str::FromStr;
 
-fn count_beats(time: u64, distance: u64) -> u64 {
-    let mut beats = 0;
-    for speed in 1..time {
-        if (time - speed) * speed > distance {
-            beats += 1;
+fn beats(time: u128, distance: u128, hold: u128) -> bool {
+    hold.checked_mul(time - hold)
+        .is_none_or(|traveled| traveled > distance)
+}
+
+fn first_beat(time: u128, distance: u128) -> u128 {
+    let discriminant = time
+        .checked_mul(time)
+        .zip(distance.checked_mul(4))
+        .map(|(square, distance)| square.saturating_sub(distance));
+    match discriminant {
+        Some(discriminant) => {
+            let mut hold = ((time - discriminant.isqrt()) / 2).min(time / 2);
+            while !beats(time, distance, hold) {
+                hold += 1;
+            }
+            while hold > 0 && beats(time, distance, hold - 1) {
+                hold -= 1;
+            }
+            hold
         }
+        None => {
+            let (mut low, mut high) = (0, time / 2);
+            while low < high {
+                let mid = low + (high - low) / 2;
+                if beats(time, distance, mid) {
+                    high = mid;
+                } else {
+                    low = mid + 1;
+                }
+            }
+            low
+        }
+    }
+}
+
+fn count_beats(time: u128, distance: u128) -> u128 {
+    if !beats(time, distance, time / 2) {
+        return 0;
     }
-    beats
+    time - 2 * first_beat(time, distance) + 1
 }
 
-pub fn part1(input: &str) -> u64 {
+pub fn part1(input: &str) -> u128 {
     let mut lines = input.lines();
-    let times = lines.next().unwrap().split(' ').flat_map(u64::from_str);
-    let distances = lines.next().unwrap().split(' ').flat_map(u64::from_str);
+    let times = lines.next().unwrap().split(' ').flat_map(u128::from_str);
+    let distances = lines.next().unwrap().split(' ').flat_map(u128::from_str);
     let mut margin = 1;
     for (time, distance) in times.zip(distances) {
         margin *= count_beats(time, distance);
@@ -21,7 +54,7 @@ pub fn part1(input: &str) -> u64 {
     margin
 }
 
-pub fn part2(input: &str) -> u64 {
+pub fn part2(input: &str) -> u128 {
     let mut lines = input.lines();
     let time = lines
         .next()
@@ -43,3 +76,37 @@ pub fn part2(input: &str) -> u64 {
         .unwrap();
     count_beats(time, distance)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn example() {
+        let input = "Time:      7  15   30\nDistance:  9  40  200";
+        assert_eq!(part1(input), 288);
+        assert_eq!(part2(input), 71503);
+    }
+
+    #[test]
+    fn count_beats_matches_brute_force() {
+        for time in 0..60 {
+            for distance in 0..time * time / 4 + 3 {
+                let expected = (0..=time)
+                    .filter(|hold| hold * (time - hold) > distance)
+                    .count() as u128;
+                assert_eq!(count_beats(time, distance), expected);
+            }
+        }
+    }
+
+    #[test]
+    fn count_beats_handles_huge_races() {
+        let time = u128::MAX;
+        assert_eq!(count_beats(time, 0), time - 1);
+        let time = 1u128 << 60;
+        let half = time / 2;
+        assert_eq!(count_beats(time, half * half - 1), 1);
+        assert_eq!(count_beats(time, half * half), 0);
+    }
+}
@@ -1,161 +1,251 @@
-use std::any::{Any, TypeId};
-use std::fmt::Debug;
-
-macro_rules! card_impl {
-    ($card:ident, $($jack:ident)?, $($joker:ident)?) => {
-        #[derive(PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Debug)]
-        enum $card {
-            $($joker,)?
-            Two,
-            Three,
-            Four,
-            Five,
-            Six,
-            Seven,
-            Eight,
-            Nine,
-            Ten,
-            $($jack,)?
-            Queen,
-            King,
-            Ace,
-        }
+use std::collections::BTreeMap;
+use std::fmt::Write;
 
-        impl From<char> for $card {
-            fn from(c: char) -> Self {
-                match c {
-                    '2' => $card::Two,
-                    '3' => $card::Three,
-                    '4' => $card::Four,
-                    '5' => $card::Five,
-                    '6' => $card::Six,
-                    '7' => $card::Seven,
-                    '8' => $card::Eight,
-                    '9' => $card::Nine,
-                    'T' => $card::Ten,
-                    'J' => $card::$($jack)? $($joker)?,
-                    'Q' => $card::Queen,
-                    'K' => $card::King,
-                    'A' => $card::Ace,
-                    _ => unreachable!(),
-                }
-            }
+#[derive(Clone, Copy)]
+enum TieBreak {
+    InOrder,
+    #[cfg(test)]
+    Sorted,
+}
+
+struct Rules {
+    ranking: &'static str,
+    wildcards: &'static str,
+    hand_size: usize,
+    tie_break: TieBreak,
+}
+
+const PART1_RULES: Rules = Rules {
+    ranking: "23456789TJQKA",
+    wildcards: "",
+    hand_size: 5,
+    tie_break: TieBreak::InOrder,
+};
+
+const PART2_RULES: Rules = Rules {
+    ranking: "J23456789TQKA",
+    wildcards: "J",
+    hand_size: 5,
+    tie_break: TieBreak::InOrder,
+};
+
+#[derive(PartialEq, Eq, PartialOrd, Ord, Clone, Debug)]
+struct HandType(Vec<usize>);
+
+impl HandType {
+    fn name(&self) -> String {
+        match self.0.as_slice() {
+            [5] => "five of a kind".to_string(),
+            [4, 1] => "four of a kind".to_string(),
+            [3, 2] => "full house".to_string(),
+            [3, 1, 1] => "three of a kind".to_string(),
+            [2, 2, 1] => "two pair".to_string(),
+            [2, 1, 1, 1] => "one pair".to_string(),
+            [1, 1, 1, 1, 1] => "high card".to_string(),
+            groups => format!("{groups:?}"),
         }
+    }
+}
+
+impl Rules {
+    fn rank(&self, card: char) -> usize {
+        self.ranking.find(card).unwrap()
+    }
+
+    fn is_wild(&self, card: char) -> bool {
+        self.wildcards.contains(card)
+    }
 
-        impl From<$card> for usize {
-            fn from(card: $card) -> Self {
-                card as usize
+    fn hand_type(&self, hand: &Hand) -> HandType {
+        let mut counts = vec![0; self.ranking.len()];
+        let mut wild = 0;
+        for card in hand.cards.chars() {
+            if self.is_wild(card) {
+                wild += 1;
+            } else {
+                counts[self.rank(card)] += 1;
             }
         }
-    };
-}
 
-card_impl!(Part1Card, Jack,);
-card_impl!(Part2Card,, Joker);
-
-#[derive(PartialEq, Eq, PartialOrd, Ord)]
-enum HandType {
-    HighCard,
-    OnePair,
-    TwoPair,
-    ThreeOfAKind,
-    FullHouse,
-    FourOfAKind,
-    FiveOfAKind,
-}
+        counts.retain(|&count| count > 0);
+        counts.sort_unstable_by(|a, b| b.cmp(a));
+        match counts.first_mut() {
+            Some(largest) => *largest += wild,
+            None => counts.push(wild),
+        }
+        HandType(counts)
+    }
 
-impl HandType {
-    fn of<C>(cards: [C; 5]) -> Self
-    where
-        C: Into<usize> + Any,
-    {
-        let mut counts = [0; 13];
-        for card in cards {
-            counts[card.into()] += 1;
+    fn best_substitution(&self, hand: &Hand) -> Option<String> {
+        if !hand.cards.chars().any(|card| self.is_wild(card)) {
+            return None;
         }
 
-        if TypeId::of::<C>() == TypeId::of::<Part2Card>() {
-            counts[1..].sort_unstable();
-            counts[12] += counts[0];
-        } else {
-            counts.sort_unstable();
+        let mut counts = vec![0; self.ranking.len()];
+        for card in hand.cards.chars().filter(|&card| !self.is_wild(card)) {
+            counts[self.rank(card)] += 1;
         }
+        let replacement = self
+            .ranking
+            .chars()
+            .filter(|&card| !self.is_wild(card))
+            .max_by_key(|&card| (counts[self.rank(card)], self.rank(card)))
+            .unwrap();
+
+        Some(
+            hand.cards
+                .chars()
+                .map(|card| {
+                    if self.is_wild(card) {
+                        replacement
+                    } else {
+                        card
+                    }
+                })
+                .collect(),
+        )
+    }
 
-        match counts {
-            [.., 5] => HandType::FiveOfAKind,
-            [.., 4] => HandType::FourOfAKind,
-            [.., 2, 3] => HandType::FullHouse,
-            [.., 3] => HandType::ThreeOfAKind,
-            [.., 2, 2] => HandType::TwoPair,
-            [.., 2] => HandType::OnePair,
-            _ => HandType::HighCard,
+    fn tie_break(&self, hand: &Hand) -> Vec<usize> {
+        let ranks = hand.cards.chars().map(|card| self.rank(card));
+        match self.tie_break {
+            TieBreak::InOrder => ranks.collect(),
+            #[cfg(test)]
+            TieBreak::Sorted => {
+                let mut ranks: Vec<_> = ranks.collect();
+                ranks.sort_unstable_by(|a, b| b.cmp(a));
+                ranks
+            }
         }
     }
+
+    fn key(&self, hand: &Hand) -> (HandType, Vec<usize>) {
+        assert_eq!(hand.cards.chars().count(), self.hand_size);
+        (self.hand_type(hand), self.tie_break(hand))
+    }
 }
 
-#[derive(PartialEq, Eq)]
-struct Hand<C> {
-    cards: [C; 5],
+struct Hand<'a> {
+    cards: &'a str,
     bid: u64,
 }
 
-impl<C> Hand<C>
-where
-    C: From<char> + Debug,
-{
-    fn parse(s: &str) -> Self {
+impl<'a> Hand<'a> {
+    fn parse(s: &'a str) -> Self {
         let (cards, bid) = s.split_once(' ').unwrap();
         Hand {
-            cards: cards
-                .chars()
-                .map(C::from)
-                .collect::<Vec<_>>()
-                .try_into()
-                .unwrap(),
+            cards,
             bid: bid.parse().unwrap(),
         }
     }
 }
 
-impl<C> PartialOrd for Hand<C>
-where
-    C: Ord + Into<usize> + Copy + Any,
-{
-    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
-        Some(self.cmp(other))
-    }
+fn rank_hands<'a>(input: &'a str, rules: &Rules) -> Vec<Hand<'a>> {
+    let mut hands: Vec<Hand> = input.lines().map(Hand::parse).collect();
+    hands.sort_by_cached_key(|hand| rules.key(hand));
+    hands
+}
+
+fn total_winnings(input: &str, rules: &Rules) -> u64 {
+    rank_hands(input, rules)
+        .into_iter()
+        .enumerate()
+        .map(|(rank, hand)| (rank as u64 + 1) * hand.bid)
+        .sum()
 }
 
-impl<C> Ord for Hand<C>
-where
-    C: Ord + Into<usize> + Copy + Any,
-{
-    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
-        let (self_type, other_type) = (HandType::of(self.cards), HandType::of(other.cards));
-        if self_type == other_type {
-            self.cards.cmp(&other.cards)
-        } else {
-            self_type.cmp(&other_type)
+fn report(input: &str, rules: &Rules) -> String {
+    let mut out = String::new();
+    let mut summary: BTreeMap<HandType, (u64, u64)> = BTreeMap::new();
+    for (rank, hand) in rank_hands(input, rules).into_iter().enumerate() {
+        let rank = rank as u64 + 1;
+        let hand_type = rules.hand_type(&hand);
+        let winnings = rank * hand.bid;
+        write!(
+            out,
+            "{rank:>5} {} {:>5} {:<16} {winnings:>9}",
+            hand.cards,
+            hand.bid,
+            hand_type.name()
+        )
+        .unwrap();
+        if let Some(substitution) = rules.best_substitution(&hand) {
+            write!(out, "  as {substitution}").unwrap();
         }
+        out.push('\n');
+
+        let entry = summary.entry(hand_type).or_default();
+        entry.0 += 1;
+        entry.1 += winnings;
+    }
+
+    out.push('\n');
+    for (hand_type, (count, winnings)) in summary.into_iter().rev() {
+        writeln!(out, "{:<16} {count:>5} {winnings:>12}", hand_type.name()).unwrap();
+    }
+    out
+}
+
+pub fn debug(input: &str, mode: &str) -> String {
+    match mode {
+        "part1" => report(input, &PART1_RULES),
+        "" | "part2" => report(input, &PART2_RULES),
+        _ => panic!("unknown day07 debug mode {mode:?}"),
     }
 }
 
 pub fn part1(input: &str) -> u64 {
-    let mut hands: Vec<Hand<Part1Card>> = input.lines().map(Hand::parse).collect();
-    hands.sort();
-    hands
-        .into_iter()
-        .enumerate()
-        .map(|(rank, hand)| (rank as u64 + 1) * hand.bid)
-        .sum()
+    total_winnings(input, &PART1_RULES)
 }
 
 pub fn part2(input: &str) -> u64 {
-    let mut hands: Vec<Hand<Part2Card>> = input.lines().map(Hand::parse).collect();
-    hands.sort();
-    hands
-        .into_iter()
-        .enumerate()
-        .map(|(rank, hand)| (rank as u64 + 1) * hand.bid)
-        .sum()
+    total_winnings(input, &PART2_RULES)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXAMPLE: &str = "32T3K 765\nT55J5 684\nKK677 28\nKTJJT 220\nQQQJA 483";
+
+    #[test]
+    fn example() {
+        assert_eq!(part1(EXAMPLE), 6440);
+        assert_eq!(part2(EXAMPLE), 5905);
+    }
+
+    #[test]
+    fn report_lists_ranks_and_summary() {
+        let expected = "    1 32T3K   765 one pair               765
+    2 KK677    28 two pair                56
+    3 T55J5   684 four of a kind        2052  as T5555
+    4 QQQJA   483 four of a kind        1932  as QQQQA
+    5 KTJJT   220 four of a kind        1100  as KTTTT
+
+four of a kind       3         5084
+two pair             1           56
+one pair             1          765
+";
+        assert_eq!(report(EXAMPLE, &PART2_RULES), expected);
+        assert_eq!(debug(EXAMPLE, ""), expected);
+        assert!(!debug(EXAMPLE, "part1").contains(" as "));
+    }
+
+    #[test]
+    fn custom_rules() {
+        let rules = Rules {
+            ranking: "23456789TJQKA",
+            wildcards: "",
+            hand_size: 3,
+            tie_break: TieBreak::Sorted,
+        };
+        let hands: Vec<_> = rank_hands("A23 1\n3K2 2\n444 3\n2K2 4", &rules)
+            .into_iter()
+            .map(|hand| hand.cards)
+            .collect();
+        assert_eq!(hands, ["3K2", "A23", "2K2", "444"]);
+        let hand = Hand::parse("JJJJJ 1");
+        assert_eq!(PART2_RULES.hand_type(&hand).name(), "five of a kind");
+        assert_eq!(PART2_RULES.best_substitution(&hand).unwrap(), "AAAAA");
+    }
 }
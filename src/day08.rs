@@ -1,6 +1,6 @@
 use std::collections::HashMap;
 
-use crate::shared::lcm;
+use crate::shared::{crt, Overflow};
 
 fn parse(input: &str) -> (&[u8], HashMap<&str, (&str, &str)>) {
     let (directions, tree) = input.split_once("\n\n").unwrap();
@@ -31,6 +31,102 @@ fn distance<'a>(
     index as u64
 }
 
+struct Cycle {
+    transient_hits: Vec<u64>,
+    start: u64,
+    period: u64,
+    residues: Vec<u64>,
+}
+
+impl Cycle {
+    fn analyze(
+        mut node: usize,
+        directions: &[u8],
+        graph: &[(usize, usize)],
+        is_end: &[bool],
+    ) -> Self {
+        let len = directions.len();
+        let mut seen = vec![None; graph.len() * len];
+        let mut hits = Vec::new();
+        let mut step = 0;
+
+        let start = loop {
+            let state = node * len + step as usize % len;
+            if let Some(first) = seen[state] {
+                break first;
+            }
+            seen[state] = Some(step);
+            if is_end[node] {
+                hits.push(step);
+            }
+            node = if directions[step as usize % len] == b'L' {
+                graph[node].0
+            } else {
+                graph[node].1
+            };
+            step += 1;
+        };
+
+        let period = step - start;
+        let (transient_hits, cyclic_hits): (Vec<_>, Vec<_>) =
+            hits.into_iter().partition(|&hit| hit < start);
+        Cycle {
+            transient_hits,
+            start,
+            period,
+            residues: cyclic_hits.into_iter().map(|hit| hit % period).collect(),
+        }
+    }
+
+    fn hits(&self, time: u64) -> bool {
+        if time < self.start {
+            self.transient_hits.contains(&time)
+        } else {
+            self.residues.contains(&(time % self.period))
+        }
+    }
+}
+
+fn first_common_hit(cycles: &[Cycle]) -> Result<Option<u64>, Overflow> {
+    let early = cycles
+        .iter()
+        .flat_map(|cycle| cycle.transient_hits.iter().copied())
+        .filter(|&time| cycles.iter().all(|cycle| cycle.hits(time)))
+        .min();
+    if early.is_some() {
+        return Ok(early);
+    }
+
+    let mut congruences = vec![(0, 1)];
+    for cycle in cycles {
+        let mut combined = Vec::new();
+        for &congruence in &congruences {
+            for &residue in &cycle.residues {
+                combined.extend(crt(congruence, (residue, cycle.period))?);
+            }
+        }
+        combined.sort_unstable();
+        combined.dedup();
+        congruences = combined;
+    }
+
+    let earliest = cycles.iter().map(|cycle| cycle.start).max().unwrap_or(0);
+    let mut first = None;
+    for (residue, modulus) in congruences {
+        let time = if residue >= earliest {
+            residue
+        } else {
+            (earliest - residue)
+                .div_ceil(modulus)
+                .checked_mul(modulus)
+                .and_then(|offset| offset.checked_add(residue))
+                .ok_or(Overflow)?
+        };
+        first = Some(first.map_or(time, |first: u64| first.min(time)));
+    }
+    Ok(first)
+}
+
 pub fn part1(input: &str) -> u64 {
     let (directions, tree) = parse(input);
     distance("AAA", directions, &tree, |s| s == "ZZZ")
@@ -38,8 +134,51 @@ pub fn part1(input: &str) -> u64 {
 
 pub fn part2(input: &str) -> u64 {
     let (directions, tree) = parse(input);
-    let nodes = tree.keys().copied().filter(|n| n.ends_with('A'));
-    nodes
-        .map(|node| distance(node, directions, &tree, |n| n.ends_with('Z')))
-        .fold(1, lcm)
+    let names: Vec<&str> = tree.keys().copied().collect();
+    let ids: HashMap<&str, usize> = names.iter().enumerate().map(|(i, &n)| (n, i)).collect();
+    let graph: Vec<_> = names
+        .iter()
+        .map(|name| (ids[tree[name].0], ids[tree[name].1]))
+        .collect();
+    let is_end: Vec<_> = names.iter().map(|name| name.ends_with('Z')).collect();
+
+    let cycles: Vec<_> = names
+        .iter()
+        .filter(|name| name.ends_with('A'))
+        .map(|name| Cycle::analyze(ids[name], directions, &graph, &is_end))
+        .collect();
+    first_common_hit(&cycles)
+        .unwrap_or_else(|err| panic!("first common hit: {err}"))
+        .expect("ghosts never reach end nodes simultaneously")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn part2_example() {
+        let input = "LR\n\n11A = (11B, XXX)\n11B = (XXX, 11Z)\n11Z = (11B, XXX)\n22A = (22B, XXX)\n22B = (22C, 22C)\n22C = (22Z, 22Z)\n22Z = (22B, 22B)\nXXX = (XXX, XXX)";
+        assert_eq!(part2(input), 6);
+    }
+
+    fn cycle(period: u64, residue: u64) -> Cycle {
+        Cycle {
+            transient_hits: Vec::new(),
+            start: 0,
+            period,
+            residues: vec![residue],
+        }
+    }
+
+    #[test]
+    fn first_common_hit_separates_overflow_from_no_hit() {
+        assert_eq!(first_common_hit(&[cycle(4, 1), cycle(6, 3)]), Ok(Some(9)));
+        assert_eq!(first_common_hit(&[cycle(4, 0), cycle(6, 1)]), Ok(None));
+        let big = (1 << 40) + 1;
+        assert_eq!(
+            first_common_hit(&[cycle(big, 1), cycle(big + 2, 2)]),
+            Err(Overflow)
+        );
+    }
 }
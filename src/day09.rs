@@ -1,5 +1,7 @@
 use std::str::FromStr;
 
+use num_bigint::BigInt;
+
 fn parse(input: &str) -> impl Iterator<Item = Vec<i64>> + '_ {
     input.lines().map(|l| {
         l.split(' ')
@@ -9,30 +11,79 @@ fn parse(input: &str) -> impl Iterator<Item = Vec<i64>> + '_ {
     })
 }
 
-fn deltas(mut nums: Vec<i64>) -> Vec<Vec<i64>> {
-    let mut stack = Vec::new();
-    while nums.iter().any(|n| *n != 0) {
-        let deltas = nums.iter().skip(1).zip(&nums).map(|(a, b)| a - b).collect();
-        stack.push(nums);
-        nums = deltas;
+struct Polynomial {
+    newton: Vec<BigInt>,
+}
+
+impl Polynomial {
+    fn fit(nums: &[i64]) -> Option<Self> {
+        let mut row: Vec<BigInt> = nums.iter().copied().map(BigInt::from).collect();
+        let mut newton = Vec::new();
+        while !row.is_empty() {
+            if row.iter().all(|n| *n == BigInt::from(0)) {
+                return Some(Polynomial { newton });
+            }
+            newton.push(row[0].clone());
+            row = row.windows(2).map(|pair| &pair[1] - &pair[0]).collect();
+        }
+        None
+    }
+
+    #[cfg(test)]
+    fn degree(&self) -> Option<usize> {
+        self.newton.len().checked_sub(1)
     }
-    stack
+
+    fn at(&self, x: i64) -> BigInt {
+        let mut binomial = BigInt::from(1);
+        let mut value = BigInt::from(0);
+        for (k, coefficient) in self.newton.iter().enumerate() {
+            if k > 0 {
+                binomial = binomial * (x - k as i64 + 1) / k;
+            }
+            value += coefficient * &binomial;
+        }
+        value
+    }
+}
+
+fn extrapolate(input: &str, offset: impl Fn(&[i64]) -> i64) -> u64 {
+    let total: BigInt = parse(input)
+        .map(|nums| Polynomial::fit(&nums).unwrap().at(offset(&nums)))
+        .sum();
+    i64::try_from(total).unwrap() as u64
 }
 
 pub fn part1(input: &str) -> u64 {
-    parse(input)
-        .map(|nums| deltas(nums).iter().map(|d| d.last().unwrap()).sum::<i64>())
-        .sum::<i64>() as u64
+    extrapolate(input, |nums| nums.len() as i64)
 }
 
 pub fn part2(input: &str) -> u64 {
-    parse(input)
-        .map(|nums| {
-            deltas(nums)
-                .iter()
-                .rev()
-                .map(|d| d[0])
-                .fold(0, |acc, i| i - acc)
-        })
-        .sum::<i64>() as u64
+    extrapolate(input, |_| -1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXAMPLE: &str = "0 3 6 9 12 15\n1 3 6 10 15 21\n10 13 16 21 30 45";
+
+    #[test]
+    fn example() {
+        assert_eq!(part1(EXAMPLE), 114);
+        assert_eq!(part2(EXAMPLE), 2);
+    }
+
+    #[test]
+    fn polynomial_fit() {
+        let polynomial = Polynomial::fit(&[1, 3, 6, 10, 15, 21]).unwrap();
+        assert_eq!(polynomial.degree(), Some(2));
+        assert_eq!(polynomial.at(-1), BigInt::from(0));
+        assert_eq!(polynomial.at(6), BigInt::from(28));
+        assert_eq!(polynomial.at(1_000_000), BigInt::from(500_001_500_001i64));
+
+        assert_eq!(Polynomial::fit(&[0, 0, 0]).unwrap().degree(), None);
+        assert_eq!(Polynomial::fit(&[7, 7]).unwrap().degree(), Some(0));
+        assert!(Polynomial::fit(&[1, 2, 4, 8]).is_none());
+    }
 }
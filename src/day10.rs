@@ -1,6 +1,8 @@
 use std::collections::HashSet;
+use std::fmt::Write;
+use std::io::IsTerminal;
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 enum Direction {
     North,
     South,
@@ -25,12 +27,12 @@ impl Direction {
         }
     }
 
-    fn step(self, row: usize, col: usize) -> (usize, usize) {
+    fn step(self, row: usize, col: usize, rows: usize, cols: usize) -> Option<(usize, usize)> {
         match self {
-            Direction::North => (row - 1, col),
-            Direction::South => (row + 1, col),
-            Direction::East => (row, col + 1),
-            Direction::West => (row, col - 1),
+            Direction::North => Some((row.checked_sub(1)?, col)),
+            Direction::South => (row + 1 < rows).then_some((row + 1, col)),
+            Direction::East => (col + 1 < cols).then_some((row, col + 1)),
+            Direction::West => Some((row, col.checked_sub(1)?)),
         }
     }
 
@@ -64,6 +66,15 @@ enum Pipe {
 }
 
 impl Pipe {
+    const ALL: [Pipe; 6] = [
+        Pipe::NorthSouth,
+        Pipe::EastWest,
+        Pipe::NorthEast,
+        Pipe::NorthWest,
+        Pipe::SouthWest,
+        Pipe::SouthEast,
+    ];
+
     fn parse(c: char) -> Option<Self> {
         match c {
             '|' => Some(Pipe::NorthSouth),
@@ -76,6 +87,12 @@ impl Pipe {
         }
     }
 
+    fn connecting(a: Direction, b: Direction) -> Option<Self> {
+        Pipe::ALL
+            .into_iter()
+            .find(|pipe| pipe.contains(a) && pipe.contains(b))
+    }
+
     fn other_dir(self, dir: Direction) -> Direction {
         match (self, dir) {
             (Pipe::NorthSouth, Direction::North) => Direction::South,
@@ -113,54 +130,63 @@ impl Pipe {
     }
 }
 
-fn get_start(grid: &[Vec<char>]) -> (usize, usize) {
+fn get_start(grid: &[Vec<char>]) -> Option<(usize, usize)> {
     for (i, row) in grid.iter().enumerate() {
         for (j, x) in row.iter().enumerate() {
             if *x == 'S' {
-                return (i, j);
+                return Some((i, j));
             }
         }
     }
-    unreachable!()
+    None
 }
 
-fn get_starting_dir(
-    start_row: usize,
-    start_col: usize,
+fn walk(
+    start: (usize, usize),
+    start_pipe: Pipe,
+    first_dir: Direction,
     grid: &[Vec<char>],
-) -> (Direction, Direction) {
-    let mut dir1 = None;
-    for dir in Direction::ALL {
-        let (row, col) = dir.step(start_row, start_col);
-        if let Some(pipe) = Pipe::parse(grid[row][col]) {
-            if pipe.contains(dir.opposite()) {
-                if let Some(dir1) = dir1 {
-                    return (dir1, dir);
-                } else {
-                    dir1 = Some(dir);
-                }
-            }
-        }
-    }
-    unreachable!()
-}
-
-fn find_path(grid: &[Vec<char>]) -> Vec<(usize, usize, Direction, Direction)> {
-    let (mut row, mut col) = get_start(grid);
-    let (start_dir, last_dir) = get_starting_dir(row, col, grid);
-    let (mut path, mut dir) = (vec![(row, col, last_dir, start_dir)], start_dir);
+) -> Option<Vec<(usize, usize, Direction, Direction)>> {
+    let rows = grid.len();
+    let cols = grid.iter().map(Vec::len).max()?;
+    let last_dir = start_pipe.other_dir(first_dir).opposite();
+    let (mut row, mut col) = start;
+    let (mut path, mut dir) = (vec![(row, col, last_dir, first_dir)], first_dir);
 
     loop {
-        (row, col) = dir.step(row, col);
-        let Some(pipe) = Pipe::parse(grid[row][col]) else {
-            break;
-        };
+        (row, col) = dir.step(row, col, rows, cols)?;
+        if (row, col) == start {
+            return (dir == last_dir).then_some(path);
+        }
+        let pipe = Pipe::parse(*grid[row].get(col)?)?;
+        if !pipe.contains(dir.opposite()) {
+            return None;
+        }
         let old_dir = dir;
         dir = pipe.other_dir(dir.opposite());
         path.push((row, col, old_dir, dir));
     }
+}
 
-    path
+struct Loop {
+    start_pipe: Pipe,
+    path: Vec<(usize, usize, Direction, Direction)>,
+}
+
+fn find_loop(grid: &[Vec<char>]) -> Option<Loop> {
+    let (row, col) = get_start(grid)?;
+    for (i, &a) in Direction::ALL.iter().enumerate() {
+        for &b in &Direction::ALL[i + 1..] {
+            let pipe = Pipe::connecting(a, b)?;
+            if let Some(path) = walk((row, col), pipe, a, grid) {
+                return Some(Loop {
+                    start_pipe: pipe,
+                    path,
+                });
+            }
+        }
+    }
+    None
 }
 
 fn flood_fill(
@@ -193,36 +219,169 @@ fn flood_fill(
     seen
 }
 
+fn signed_double_area(path: &[(usize, usize, Direction, Direction)]) -> i64 {
+    let vertices = path
+        .iter()
+        .map(|&(row, col, _, _)| (col as i64, row as i64));
+    vertices
+        .clone()
+        .zip(vertices.cycle().skip(1))
+        .map(|((x1, y1), (x2, y2))| x1 * y2 - x2 * y1)
+        .sum()
+}
+
+fn enclosed_by_pick(path: &[(usize, usize, Direction, Direction)]) -> u64 {
+    let double_area = signed_double_area(path).unsigned_abs();
+    (double_area + 2 - path.len() as u64) / 2
+}
+
+fn inside_tiles(
+    path: &[(usize, usize, Direction, Direction)],
+    rows: usize,
+    cols: usize,
+) -> HashSet<(usize, usize)> {
+    let path_set: HashSet<_> = path.iter().map(|(r, c, _, _)| (*r, *c)).collect();
+    let clockwise = signed_double_area(path) > 0;
+
+    let mut inside = HashSet::new();
+    for (row, col, dir1, dir2) in path {
+        for dir in [dir1, dir2] {
+            inside.extend(if clockwise {
+                dir.rhs(*row, *col, rows, cols)
+            } else {
+                dir.lhs(*row, *col, rows, cols)
+            });
+        }
+    }
+
+    let inside = inside.difference(&path_set).copied().collect();
+    flood_fill(inside, &path_set, rows, cols)
+}
+
+#[derive(Clone, Copy)]
+enum RenderMode {
+    Terminal,
+    Plain,
+}
+
+impl Pipe {
+    fn box_drawing(self, heavy: bool) -> char {
+        match (self, heavy) {
+            (Pipe::NorthSouth, false) => '│',
+            (Pipe::EastWest, false) => '─',
+            (Pipe::NorthEast, false) => '└',
+            (Pipe::NorthWest, false) => '┘',
+            (Pipe::SouthWest, false) => '┐',
+            (Pipe::SouthEast, false) => '┌',
+            (Pipe::NorthSouth, true) => '┃',
+            (Pipe::EastWest, true) => '━',
+            (Pipe::NorthEast, true) => '┗',
+            (Pipe::NorthWest, true) => '┛',
+            (Pipe::SouthWest, true) => '┓',
+            (Pipe::SouthEast, true) => '┏',
+        }
+    }
+}
+
+fn render(input: &str, mode: RenderMode) -> String {
+    let grid: Vec<Vec<_>> = input.lines().map(|line| line.chars().collect()).collect();
+    let (rows, cols) = (grid.len(), grid.iter().map(Vec::len).max().unwrap());
+    let pipe_loop = find_loop(&grid).expect("no loop passes through S");
+    let on_loop: HashSet<_> = pipe_loop.path.iter().map(|(r, c, _, _)| (*r, *c)).collect();
+    let inside = inside_tiles(&pipe_loop.path, rows, cols);
+
+    let mut out = String::new();
+    for (row, line) in grid.iter().enumerate() {
+        for col in 0..cols {
+            let tile = line.get(col).copied().unwrap_or('.');
+            if on_loop.contains(&(row, col)) {
+                let pipe = Pipe::parse(tile).unwrap_or(pipe_loop.start_pipe);
+                let c = pipe.box_drawing(true);
+                match (mode, tile) {
+                    (RenderMode::Terminal, 'S') => write!(out, "\x1b[1;31m{c}\x1b[0m"),
+                    (RenderMode::Terminal, _) => write!(out, "\x1b[1;33m{c}\x1b[0m"),
+                    (RenderMode::Plain, _) => write!(out, "{c}"),
+                }
+                .unwrap();
+                continue;
+            }
+
+            let c = Pipe::parse(tile).map_or(' ', |pipe| pipe.box_drawing(false));
+            match (mode, inside.contains(&(row, col))) {
+                (RenderMode::Terminal, true) => write!(out, "\x1b[42;30m{c}\x1b[0m"),
+                (RenderMode::Terminal, false) => write!(out, "\x1b[2m{c}\x1b[0m"),
+                (RenderMode::Plain, true) => write!(out, "I"),
+                (RenderMode::Plain, false) => write!(out, "O"),
+            }
+            .unwrap();
+        }
+        out.push('\n');
+    }
+    out
+}
+
+pub fn debug(input: &str, mode: &str) -> String {
+    let mode = match mode {
+        "terminal" => RenderMode::Terminal,
+        "plain" => RenderMode::Plain,
+        "" if std::io::stdout().is_terminal() => RenderMode::Terminal,
+        "" => RenderMode::Plain,
+        _ => panic!("unknown day10 debug mode {mode:?}"),
+    };
+    render(input, mode)
+}
+
 pub fn part1(input: &str) -> u64 {
     let grid: Vec<Vec<_>> = input.lines().map(|line| line.chars().collect()).collect();
-    let path = find_path(&grid);
-    (path.len() / 2) as u64
+    let pipe_loop = find_loop(&grid).expect("no loop passes through S");
+    (pipe_loop.path.len() / 2) as u64
 }
 
 pub fn part2(input: &str) -> u64 {
     let grid: Vec<Vec<_>> = input.lines().map(|line| line.chars().collect()).collect();
-    let (rows, cols) = (grid.len(), grid[0].len());
+    let (rows, cols) = (grid.len(), grid.iter().map(Vec::len).max().unwrap());
 
-    let path = find_path(&grid);
-    let path_set: HashSet<_> = path.iter().map(|(r, c, _, _)| (*r, *c)).collect();
-    let (mut lhs, mut rhs) = (HashSet::new(), HashSet::new());
+    let pipe_loop = find_loop(&grid).expect("no loop passes through S");
+    let by_pick = enclosed_by_pick(&pipe_loop.path);
+    let by_flood_fill = inside_tiles(&pipe_loop.path, rows, cols).len() as u64;
+    assert_eq!(by_pick, by_flood_fill);
+    by_pick
+}
 
-    for (row, col, dir1, dir2) in &path {
-        lhs.extend(dir1.lhs(*row, *col, rows, cols));
-        lhs.extend(dir2.lhs(*row, *col, rows, cols));
-        rhs.extend(dir1.rhs(*row, *col, rows, cols));
-        rhs.extend(dir2.rhs(*row, *col, rows, cols));
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn smallest_loop() {
+        assert_eq!(part1("S7\nLJ"), 2);
+        assert_eq!(part2("S7\nLJ"), 0);
     }
 
-    let lhs: Vec<_> = lhs.difference(&path_set).copied().collect();
-    let rhs: Vec<_> = rhs.difference(&path_set).copied().collect();
+    #[test]
+    fn examples() {
+        assert_eq!(part1(".....\n.S-7.\n.|.|.\n.L-J.\n....."), 4);
+        assert_eq!(part1("..F7.\n.FJ|.\nSJ.L7\n|F--J\nLJ..."), 8);
+        let input = "...........\n.S-------7.\n.|F-----7|.\n.||.....||.\n.||.....||.\n.|L-7.F-J|.\n.|..|.|..|.\n.L--J.L--J.\n...........";
+        assert_eq!(part2(input), 4);
+        let input = "FF7FSF7F7F7F7F7F---7\nL|LJ||||||||||||F--J\nFL-7LJLJ||||||LJL-77\nF--JF--7||LJLJ7F7FJ-\nL---JF-JLJ.||-FJLJJ7\n|F|F-JF---7F7-L7L|7|\n|FFJF7L7F-JF7|JL---7\n7-L-JL7||F7|L7F-7F7|\nL.L7LFJ|||||FJL7||LJ\nL7JLJL-JLJLJL--JLJ.L";
+        assert_eq!(part2(input), 10);
+    }
 
-    let lhs = flood_fill(lhs, &path_set, rows, cols);
-    let rhs = flood_fill(rhs, &path_set, rows, cols);
+    #[test]
+    fn render_plain_marks_every_tile_off_the_loop() {
+        let input = "-L|F7\n7S-7|\nL|7||\n-L-J|\nL|-JF";
+        let expected = "OOOOO\nO┏━┓O\nO┃I┃O\nO┗━┛O\nOOOOO\n";
+        assert_eq!(render(input, RenderMode::Plain), expected);
+        let input = "S7.\n|L7\nL-J";
+        let expected = "┏┓O\n┃┗┓\n┗━┛\n";
+        assert_eq!(render(input, RenderMode::Plain), expected);
+        assert_eq!(debug(input, "plain"), expected);
+    }
 
-    if lhs.contains(&(0, 0)) {
-        rhs.len() as u64
-    } else {
-        lhs.len() as u64
+    #[test]
+    fn render_terminal_highlights_start() {
+        let out = render("S7\nLJ", RenderMode::Terminal);
+        assert!(out.starts_with("\x1b[1;31m┏\x1b[0m\x1b[1;33m┓\x1b[0m\n"));
     }
 }
@@ -1,62 +1,85 @@
-use std::cmp::{max, min};
-use std::collections::BTreeSet;
+struct DistanceSum {
+    base: u64,
+    per_extra_line: u64,
+}
+
+impl DistanceSum {
+    fn at(&self, expansion_factor: u64) -> u64 {
+        self.base - self.per_extra_line + expansion_factor * self.per_extra_line
+    }
+}
+
+fn pairwise_sum(sorted: impl Iterator<Item = u64>) -> u64 {
+    let (mut sum, mut prefix) = (0, 0);
+    for (i, x) in sorted.enumerate() {
+        sum += x * i as u64 - prefix;
+        prefix += x;
+    }
+    sum
+}
 
-fn adjusted_distance(
-    (row1, col1): (usize, usize),
-    (row2, col2): (usize, usize),
-    empty_rows: &BTreeSet<usize>,
-    empty_cols: &BTreeSet<usize>,
-    expansion: usize,
-) -> u64 {
-    let distance = row1.abs_diff(row2) + col1.abs_diff(col2);
-    let delta_rows = empty_rows.range(row2..row1).count();
-    let delta_cols = empty_cols.range(min(col1, col2)..max(col1, col2)).count();
+fn axis_sum(occupancy: &[u64]) -> DistanceSum {
+    let mut positions = Vec::new();
+    let mut empty_before = 0;
+    for (line, &count) in occupancy.iter().enumerate() {
+        if count == 0 {
+            empty_before += 1;
+        }
+        for _ in 0..count {
+            positions.push((line as u64, empty_before));
+        }
+    }
 
-    (expansion * delta_rows + expansion * delta_cols + distance) as u64
+    DistanceSum {
+        base: pairwise_sum(positions.iter().map(|&(line, _)| line)),
+        per_extra_line: pairwise_sum(positions.iter().map(|&(_, empty)| empty)),
+    }
 }
 
-fn sum_distances(input: &str, expansion_factor: usize) -> u64 {
+fn distance_sum(input: &str) -> DistanceSum {
     let grid: Vec<Vec<_>> = input
         .lines()
         .map(|line| line.chars().map(|c| c == '#').collect())
         .collect();
-    let empty_rows: BTreeSet<_> = grid
-        .iter()
-        .enumerate()
-        .filter_map(|(i, row)| row.iter().all(|x| !x).then_some(i))
-        .collect();
-    let empty_cols: BTreeSet<_> = (0..grid[0].len())
-        .filter(|col| grid.iter().all(|row| !row[*col]))
-        .collect();
-    let galaxies: Vec<_> = grid
-        .iter()
-        .enumerate()
-        .flat_map(|(i, row)| {
-            row.iter()
-                .enumerate()
-                .filter_map(move |(j, x)| x.then_some((i, j)))
-        })
-        .collect();
-
-    let mut sum = 0;
-    for (i, galaxy1) in galaxies.iter().enumerate() {
-        for galaxy2 in galaxies[..i].iter() {
-            sum += adjusted_distance(
-                *galaxy1,
-                *galaxy2,
-                &empty_rows,
-                &empty_cols,
-                expansion_factor,
-            );
+    let mut row_counts = vec![0; grid.len()];
+    let mut col_counts = vec![0; grid[0].len()];
+    for (i, row) in grid.iter().enumerate() {
+        for (j, &galaxy) in row.iter().enumerate() {
+            if galaxy {
+                row_counts[i] += 1;
+                col_counts[j] += 1;
+            }
         }
     }
-    sum
+
+    let (rows, cols) = (axis_sum(&row_counts), axis_sum(&col_counts));
+    DistanceSum {
+        base: rows.base + cols.base,
+        per_extra_line: rows.per_extra_line + cols.per_extra_line,
+    }
 }
 
 pub fn part1(input: &str) -> u64 {
-    sum_distances(input, 1)
+    distance_sum(input).at(2)
 }
 
 pub fn part2(input: &str) -> u64 {
-    sum_distances(input, 999_999)
+    distance_sum(input).at(1_000_000)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXAMPLE: &str = "...#......\n.......#..\n#.........\n..........\n......#...\n.#........\n.........#\n..........\n.......#..\n#...#.....";
+
+    #[test]
+    fn example() {
+        let sum = distance_sum(EXAMPLE);
+        assert_eq!(sum.at(0), 210);
+        assert_eq!(sum.at(1), 292);
+        assert_eq!(sum.at(2), 374);
+        assert_eq!(sum.at(10), 1030);
+        assert_eq!(sum.at(100), 8410);
+    }
 }
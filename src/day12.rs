@@ -1,6 +1,9 @@
 use std::str::FromStr;
 
-#[derive(Clone, Copy, Debug)]
+#[cfg(test)]
+use rand::Rng;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 enum Condition {
     Operational,
     Damaged,
@@ -39,67 +42,380 @@ fn parse(line: &str, copies: usize) -> (Vec<Condition>, Vec<usize>) {
     )
 }
 
-fn count_arrangements(springs: &[Condition], runs: &[usize]) -> u64 {
-    if runs.is_empty() {
-        return springs.iter().all(Condition::maybe_operational).into();
+fn suffix_counts(springs: &[Condition], runs: &[usize]) -> Vec<Vec<u64>> {
+    let (n, m) = (springs.len(), runs.len());
+    let mut counts = vec![vec![0u64; m + 1]; n + 1];
+    counts[n][m] = 1;
+
+    for i in (0..n).rev() {
+        for j in 0..=m {
+            let mut count = 0;
+            if springs[i].maybe_operational() {
+                count = counts[i + 1][j];
+            }
+            if let Some(&run) = runs.get(j) {
+                let end = i + run;
+                if end <= n && springs[i..end].iter().all(Condition::maybe_damaged) {
+                    let ways = if end == n {
+                        u64::from(j + 1 == m)
+                    } else if springs[end].maybe_operational() {
+                        counts[end + 1][j + 1]
+                    } else {
+                        0
+                    };
+                    count = count
+                        .checked_add(ways)
+                        .expect("arrangement count overflows u64");
+                }
+            }
+            counts[i][j] = count;
+        }
     }
 
-    let (left_runs, right_runs) = runs.split_at(runs.len() / 2);
-    let (&run, right_runs) = right_runs.split_first().unwrap();
+    counts
+}
+
+#[cfg(test)]
+struct Arrangements<'a> {
+    springs: &'a [Condition],
+    runs: &'a [usize],
+    suffix_counts: Vec<Vec<u64>>,
+}
 
-    if run == springs.len() {
-        return springs.iter().all(Condition::maybe_damaged).into();
+#[cfg(test)]
+impl<'a> Arrangements<'a> {
+    fn new(springs: &'a [Condition], runs: &'a [usize]) -> Self {
+        Arrangements {
+            springs,
+            runs,
+            suffix_counts: suffix_counts(springs, runs),
+        }
     }
 
-    let mut left_margin = left_runs.iter().sum::<usize>() + left_runs.len();
-    let mut right_margin = right_runs.iter().sum::<usize>() + right_runs.len();
+    fn total(&self) -> u64 {
+        self.suffix_counts[0][0]
+    }
 
-    let mut count = 0;
+    fn nth(&self, mut index: u64) -> Option<String> {
+        let (springs, runs) = (self.springs, self.runs);
+        if index >= self.total() {
+            return None;
+        }
 
-    if left_margin == 0 {
-        left_margin = 1;
-        if springs[..run].iter().all(Condition::maybe_damaged) && springs[run].maybe_operational() {
-            count += count_arrangements(&springs[run + 1..], right_runs);
+        let mut arrangement = String::with_capacity(springs.len());
+        let (mut position, mut next_run) = (0, 0);
+        while position < springs.len() {
+            if let Some(&run) = runs.get(next_run) {
+                let end = position + run;
+                let fits = end <= springs.len()
+                    && springs[position..end].iter().all(Condition::maybe_damaged)
+                    && springs.get(end).is_none_or(Condition::maybe_operational);
+                if fits {
+                    let after = (end + 1).min(springs.len());
+                    let damaged = self.suffix_counts[after][next_run + 1];
+                    if index < damaged {
+                        arrangement.extend(std::iter::repeat_n('#', run));
+                        if end < springs.len() {
+                            arrangement.push('.');
+                        }
+                        position = after;
+                        next_run += 1;
+                        continue;
+                    }
+                    index -= damaged;
+                }
+            }
+            arrangement.push('.');
+            position += 1;
         }
+        Some(arrangement)
     }
-    if right_margin == 0 {
-        right_margin = 1;
-        if springs[springs.len() - run..]
-            .iter()
-            .all(Condition::maybe_damaged)
-            && springs[springs.len() - run - 1].maybe_operational()
-        {
-            count += count_arrangements(&springs[..springs.len() - run - 1], left_runs);
+
+    fn iter(&self) -> impl Iterator<Item = String> + '_ {
+        (0..self.total()).map_while(|index| self.nth(index))
+    }
+
+    fn sample(&self, rng: &mut impl Rng) -> Option<String> {
+        let total = self.total();
+        (total > 0).then(|| self.nth(rng.gen_range(0..total)).unwrap())
+    }
+}
+
+fn count_arrangements(springs: &[Condition], runs: &[usize]) -> u64 {
+    suffix_counts(springs, runs)[0][0]
+}
+
+#[cfg(test)]
+fn refine_line(springs: &[Condition], runs: &[usize]) -> Option<Vec<Condition>> {
+    let (n, m) = (springs.len(), runs.len());
+    let suffix = suffix_counts(springs, runs);
+    if suffix[0][0] == 0 {
+        return None;
+    }
+    let reversed_springs: Vec<_> = springs.iter().rev().copied().collect();
+    let reversed_runs: Vec<_> = runs.iter().rev().copied().collect();
+    let reversed = suffix_counts(&reversed_springs, &reversed_runs);
+    let prefix = |i: usize, j: usize| reversed[n - i][m - j];
+
+    let mut can_be_damaged = vec![false; n];
+    let mut can_be_operational = vec![false; n];
+
+    for i in 0..n {
+        if springs[i].maybe_operational() {
+            can_be_operational[i] = (0..=m).any(|j| prefix(i, j) > 0 && suffix[i + 1][j] > 0);
+        }
+    }
+    for (j, &run) in runs.iter().enumerate() {
+        for start in 0..(n + 1).saturating_sub(run) {
+            let end = start + run;
+            let fits = springs[start..end].iter().all(Condition::maybe_damaged)
+                && if start == 0 {
+                    j == 0
+                } else {
+                    springs[start - 1].maybe_operational() && prefix(start - 1, j) > 0
+                }
+                && if end == n {
+                    j + 1 == m
+                } else {
+                    springs[end].maybe_operational() && suffix[end + 1][j + 1] > 0
+                };
+            if fits {
+                can_be_damaged[start..end].fill(true);
+            }
+        }
+    }
+
+    can_be_damaged
+        .into_iter()
+        .zip(can_be_operational)
+        .map(|options| match options {
+            (true, true) => Some(Condition::Unknown),
+            (true, false) => Some(Condition::Damaged),
+            (false, true) => Some(Condition::Operational),
+            (false, false) => None,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+enum Solution {
+    Contradiction,
+    Unique(Vec<Vec<bool>>),
+    Ambiguous(Vec<Vec<bool>>, Vec<Vec<bool>>),
+}
+
+#[cfg(test)]
+struct Nonogram {
+    rows: Vec<Vec<usize>>,
+    cols: Vec<Vec<usize>>,
+}
+
+#[cfg(test)]
+impl Nonogram {
+    fn propagate(&self, grid: &mut [Vec<Condition>]) -> bool {
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for (row, runs) in self.rows.iter().enumerate() {
+                let Some(refined) = refine_line(&grid[row], runs) else {
+                    return false;
+                };
+                if refined != grid[row] {
+                    grid[row] = refined;
+                    changed = true;
+                }
+            }
+            for (col, runs) in self.cols.iter().enumerate() {
+                let line: Vec<_> = grid.iter().map(|row| row[col]).collect();
+                let Some(refined) = refine_line(&line, runs) else {
+                    return false;
+                };
+                if refined != line {
+                    for (row, condition) in grid.iter_mut().zip(refined) {
+                        row[col] = condition;
+                    }
+                    changed = true;
+                }
+            }
         }
+        true
     }
 
-    for start in left_margin..springs.len() + 1 - right_margin - run {
-        if springs[start - 1].maybe_operational()
-            && springs[start + run].maybe_operational()
-            && springs[start..start + run]
+    fn search(&self, mut grid: Vec<Vec<Condition>>, solutions: &mut Vec<Vec<Vec<bool>>>) {
+        if solutions.len() >= 2 || !self.propagate(&mut grid) {
+            return;
+        }
+
+        let unknown = grid.iter().enumerate().find_map(|(i, row)| {
+            row.iter()
+                .position(|&condition| condition == Condition::Unknown)
+                .map(|j| (i, j))
+        });
+        let Some((row, col)) = unknown else {
+            let solution = grid
                 .iter()
-                .all(Condition::maybe_damaged)
-        {
-            count += count_arrangements(&springs[..start - 1], left_runs)
-                * count_arrangements(&springs[start + run + 1..], right_runs);
+                .map(|row| row.iter().map(|&c| c == Condition::Damaged).collect())
+                .collect();
+            solutions.push(solution);
+            return;
+        };
+
+        for guess in [Condition::Damaged, Condition::Operational] {
+            let mut grid = grid.clone();
+            grid[row][col] = guess;
+            self.search(grid, solutions);
         }
     }
 
-    count
+    fn solve(&self) -> Solution {
+        let grid = vec![vec![Condition::Unknown; self.cols.len()]; self.rows.len()];
+        let mut solutions = Vec::new();
+        self.search(grid, &mut solutions);
+
+        let mut solutions = solutions.into_iter();
+        match (solutions.next(), solutions.next()) {
+            (None, _) => Solution::Contradiction,
+            (Some(solution), None) => Solution::Unique(solution),
+            (Some(first), Some(second)) => Solution::Ambiguous(first, second),
+        }
+    }
 }
 
-pub fn part1(input: &str) -> u64 {
+fn total_arrangements(input: &str, copies: usize) -> u64 {
     input
         .lines()
-        .map(|line| parse(line, 1))
+        .map(|line| parse(line, copies))
         .map(|(springs, runs)| count_arrangements(&springs, &runs))
-        .sum()
+        .try_fold(0u64, u64::checked_add)
+        .expect("arrangement count overflows u64")
+}
+
+pub fn part1(input: &str) -> u64 {
+    total_arrangements(input, 1)
 }
 
 pub fn part2(input: &str) -> u64 {
-    input
-        .lines()
-        .map(|line| parse(line, 5))
-        .map(|(springs, runs)| count_arrangements(&springs, &runs))
-        .sum()
+    total_arrangements(input, 5)
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    use super::*;
+
+    const EXAMPLE: &str = "???.### 1,1,3\n.??..??...?##. 1,1,3\n?#?#?#?#?#?#?#? 1,3,1,6\n????.#...#... 4,1,1\n????.######..#####. 1,6,5\n?###???????? 3,2,1";
+
+    #[test]
+    fn example() {
+        assert_eq!(part1(EXAMPLE), 21);
+        assert_eq!(part2(EXAMPLE), 525152);
+    }
+
+    #[test]
+    fn arrangements_in_order() {
+        let (springs, runs) = parse("???? 1,1", 1);
+        let arrangements = Arrangements::new(&springs, &runs);
+        assert_eq!(arrangements.total(), 3);
+        assert_eq!(
+            arrangements.iter().collect::<Vec<_>>(),
+            ["#.#.", "#..#", ".#.#"]
+        );
+        assert_eq!(arrangements.nth(1).unwrap(), "#..#");
+        assert_eq!(arrangements.nth(3), None);
+    }
+
+    #[test]
+    fn arrangements_respect_known_springs() {
+        for line in EXAMPLE.lines() {
+            let (springs, runs) = parse(line, 1);
+            let arrangements = Arrangements::new(&springs, &runs);
+            let all: Vec<_> = arrangements.iter().collect();
+            assert_eq!(all.len() as u64, arrangements.total());
+            assert!(all.windows(2).all(|pair| pair[0] < pair[1]));
+            for arrangement in all {
+                let (fixed, _) = parse(&format!("{arrangement} 0"), 1);
+                assert_eq!(count_arrangements(&fixed, &runs), 1);
+                assert!(springs
+                    .iter()
+                    .zip(&fixed)
+                    .all(|(spring, fixed)| { *spring == Condition::Unknown || spring == fixed }));
+            }
+        }
+    }
+
+    #[test]
+    fn sample_picks_valid_arrangements() {
+        let mut rng = StdRng::seed_from_u64(7);
+        let (springs, runs) = parse("?###???????? 3,2,1", 1);
+        let arrangements = Arrangements::new(&springs, &runs);
+        let all: Vec<_> = arrangements.iter().collect();
+        for _ in 0..20 {
+            assert!(all.contains(&arrangements.sample(&mut rng).unwrap()));
+        }
+        let (springs, runs) = parse("#.# 3", 1);
+        assert_eq!(Arrangements::new(&springs, &runs).sample(&mut rng), None);
+    }
+
+    fn line(s: &str) -> Vec<Condition> {
+        s.chars().map(Condition::parse).collect()
+    }
+
+    #[test]
+    fn refine_line_finds_forced_cells() {
+        assert_eq!(refine_line(&line("????"), &[3]), Some(line("?##?")));
+        assert_eq!(refine_line(&line("?#???"), &[1, 1]), Some(line(".#.??")));
+        assert_eq!(refine_line(&line("??"), &[]), Some(line("..")));
+        assert_eq!(refine_line(&line("#.#"), &[3]), None);
+    }
+
+    fn grid(rows: &[&str]) -> Vec<Vec<bool>> {
+        rows.iter()
+            .map(|row| row.chars().map(|c| c == '#').collect())
+            .collect()
+    }
+
+    #[test]
+    fn nonogram_unique() {
+        let nonogram = Nonogram {
+            rows: vec![vec![1], vec![3], vec![1]],
+            cols: vec![vec![1], vec![3], vec![1]],
+        };
+        let Solution::Unique(solution) = nonogram.solve() else {
+            panic!("expected a unique solution");
+        };
+        assert_eq!(solution, grid(&[".#.", "###", ".#."]));
+
+        let nonogram = Nonogram {
+            rows: vec![vec![2, 1], vec![1, 1], vec![4], vec![]],
+            cols: vec![vec![3], vec![1, 1], vec![1], vec![3]],
+        };
+        let Solution::Unique(solution) = nonogram.solve() else {
+            panic!("expected a unique solution");
+        };
+        assert_eq!(solution, grid(&["##.#", "#..#", "####", "...."]));
+    }
+
+    #[test]
+    fn nonogram_ambiguous() {
+        let nonogram = Nonogram {
+            rows: vec![vec![1], vec![1]],
+            cols: vec![vec![1], vec![1]],
+        };
+        let Solution::Ambiguous(first, second) = nonogram.solve() else {
+            panic!("expected two solutions");
+        };
+        assert_eq!(first, grid(&["#.", ".#"]));
+        assert_eq!(second, grid(&[".#", "#."]));
+    }
+
+    #[test]
+    fn nonogram_contradiction() {
+        let nonogram = Nonogram {
+            rows: vec![vec![2]],
+            cols: vec![vec![1], vec![]],
+        };
+        assert!(matches!(nonogram.solve(), Solution::Contradiction));
+    }
 }
@@ -1,123 +1,340 @@
 use bimap::BiMap;
 
-#[derive(Clone, Copy, PartialEq, Eq, Hash)]
-enum Rock {
-    Cube,
-    Rounded,
-    None,
+use crate::shared::BitSet;
+
+#[derive(Clone, Copy)]
+enum Tilt {
+    North,
+    West,
+    South,
+    East,
 }
 
-impl Rock {
-    fn parse(c: char) -> Self {
-        match c {
-            '#' => Rock::Cube,
-            'O' => Rock::Rounded,
-            '.' => Rock::None,
-            _ => panic!(),
-        }
+impl Tilt {
+    fn parse_program(s: &str) -> Vec<Self> {
+        s.trim()
+            .chars()
+            .map(|c| match c {
+                'N' => Tilt::North,
+                'W' => Tilt::West,
+                'S' => Tilt::South,
+                'E' => Tilt::East,
+                _ => panic!(),
+            })
+            .collect()
     }
 }
 
-fn move_north(grid: &mut [Vec<Rock>], row: usize, col: usize) {
-    let mut to_row = row;
-    while to_row > 0 && grid[to_row - 1][col] == Rock::None {
-        to_row -= 1;
-    }
-    grid[row][col] = Rock::None;
-    grid[to_row][col] = Rock::Rounded;
+fn tilt_segment(
+    line: &mut BitSet,
+    (start, len): (usize, usize),
+    toward_start: bool,
+    moved: impl FnMut(usize),
+) {
+    let end = start + len;
+    let count = line.count_range(start..end);
+    let ones = if toward_start {
+        start..start + count
+    } else {
+        end - count..end
+    };
+    line.set_range(start..end, ones, moved);
 }
 
-fn move_south(grid: &mut [Vec<Rock>], row: usize, col: usize) {
-    let mut to_row = row;
-    while to_row + 1 < grid.len() && grid[to_row + 1][col] == Rock::None {
-        to_row += 1;
+fn segments(cubes: &BitSet, len: usize) -> Vec<(usize, usize)> {
+    let mut segments = Vec::new();
+    let mut start = 0;
+    for i in 0..=len {
+        if i == len || cubes.contains(i) {
+            if i > start {
+                segments.push((start, i - start));
+            }
+            start = i + 1;
+        }
     }
-    grid[row][col] = Rock::None;
-    grid[to_row][col] = Rock::Rounded;
+    segments
 }
 
-fn move_west(grid: &mut [Vec<Rock>], row: usize, col: usize) {
-    let mut to_col = col;
-    while to_col > 0 && grid[row][to_col - 1] == Rock::None {
-        to_col -= 1;
+fn tilt_lines(
+    lines: &mut [BitSet],
+    crossing: &mut [BitSet],
+    segments: &[Vec<(usize, usize)>],
+    toward_start: bool,
+) {
+    for (i, (line, segments)) in lines.iter_mut().zip(segments).enumerate() {
+        for &segment in segments {
+            tilt_segment(line, segment, toward_start, |j| crossing[j].toggle(i));
+        }
     }
-    grid[row][col] = Rock::None;
-    grid[row][to_col] = Rock::Rounded;
 }
 
-fn move_east(grid: &mut [Vec<Rock>], row: usize, col: usize) {
-    let mut to_col = col;
-    while to_col + 1 < grid[0].len() && grid[row][to_col + 1] == Rock::None {
-        to_col += 1;
+fn transpose(lines: &[BitSet], len: usize) -> Vec<BitSet> {
+    let mut transposed = vec![BitSet::new(lines.len()); len];
+    for (i, line) in lines.iter().enumerate() {
+        for j in line.ones() {
+            transposed[j].toggle(i);
+        }
     }
-    grid[row][col] = Rock::None;
-    grid[row][to_col] = Rock::Rounded;
+    transposed
+}
+
+struct Platform {
+    rows: usize,
+    cols: usize,
+    rounded_by_col: Vec<BitSet>,
+    rounded_by_row: Vec<BitSet>,
+    cubes: Vec<BitSet>,
+    col_segments: Vec<Vec<(usize, usize)>>,
+    row_segments: Vec<Vec<(usize, usize)>>,
 }
 
-fn tilt(grid: &mut [Vec<Rock>], mover: fn(&mut [Vec<Rock>], usize, usize), rev: bool) {
-    if rev {
-        for row in (0..grid.len()).rev() {
-            for col in (0..grid[0].len()).rev() {
-                if grid[row][col] == Rock::Rounded {
-                    mover(grid, row, col);
+impl Platform {
+    fn parse(input: &str) -> Self {
+        let lines: Vec<_> = input.lines().map(str::as_bytes).collect();
+        let (rows, cols) = (lines.len(), lines[0].len());
+
+        let mut rounded = vec![BitSet::new(rows); cols];
+        let mut cubes = vec![BitSet::new(rows); cols];
+        for (i, line) in lines.iter().enumerate() {
+            for (j, b) in line.iter().enumerate() {
+                match b {
+                    b'O' => rounded[j].toggle(i),
+                    b'#' => cubes[j].toggle(i),
+                    b'.' => {}
+                    _ => panic!(),
                 }
             }
         }
-    } else {
-        for row in 0..grid.len() {
-            for col in 0..grid[0].len() {
-                if grid[row][col] == Rock::Rounded {
-                    mover(grid, row, col);
-                }
+
+        Platform {
+            rows,
+            cols,
+            rounded_by_row: transpose(&rounded, rows),
+            rounded_by_col: rounded,
+            col_segments: cubes.iter().map(|col| segments(col, rows)).collect(),
+            row_segments: transpose(&cubes, rows)
+                .iter()
+                .map(|row| segments(row, cols))
+                .collect(),
+            cubes,
+        }
+    }
+
+    fn tilt(&mut self, tilt: Tilt) {
+        let (by_col, by_row) = (&mut self.rounded_by_col, &mut self.rounded_by_row);
+        match tilt {
+            Tilt::North => tilt_lines(by_col, by_row, &self.col_segments, true),
+            Tilt::South => tilt_lines(by_col, by_row, &self.col_segments, false),
+            Tilt::West => tilt_lines(by_row, by_col, &self.row_segments, true),
+            Tilt::East => tilt_lines(by_row, by_col, &self.row_segments, false),
+        }
+    }
+
+    fn run(&mut self, program: &[Tilt], repeats: u64) {
+        let mut map = BiMap::new();
+        map.insert(self.rounded_by_col.clone(), 0);
+        for done in 1..=repeats {
+            for &tilt in program {
+                self.tilt(tilt);
             }
+            if let Some(&start) = map.get_by_left(&self.rounded_by_col) {
+                let cycle_len = done - start;
+                let index = start + (repeats - done) % cycle_len;
+                self.rounded_by_col = map.get_by_right(&index).unwrap().clone();
+                self.rounded_by_row = transpose(&self.rounded_by_col, self.rows);
+                return;
+            }
+            map.insert(self.rounded_by_col.clone(), done);
         }
     }
+
+    fn load(&self, edge: Tilt) -> u64 {
+        let mut load = 0;
+        for (col, line) in self.rounded_by_col.iter().enumerate() {
+            for row in line.ones() {
+                load += match edge {
+                    Tilt::North => self.rows - row,
+                    Tilt::South => row + 1,
+                    Tilt::West => self.cols - col,
+                    Tilt::East => col + 1,
+                } as u64;
+            }
+        }
+        load
+    }
 }
 
-fn do_cycle(grid: &mut [Vec<Rock>]) {
-    tilt(grid, move_north, false);
-    tilt(grid, move_west, false);
-    tilt(grid, move_south, true);
-    tilt(grid, move_east, true);
+impl std::fmt::Display for Platform {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for row in 0..self.rows {
+            for col in 0..self.cols {
+                let c = if self.rounded_by_col[col].contains(row) {
+                    'O'
+                } else if self.cubes[col].contains(row) {
+                    '#'
+                } else {
+                    '.'
+                };
+                write!(f, "{c}")?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
 }
 
-fn compute_load(grid: &[Vec<Rock>]) -> u64 {
-    grid.iter()
-        .rev()
-        .zip(1..)
-        .map(|(row, load)| row.iter().filter(|x| **x == Rock::Rounded).count() as u64 * load)
-        .sum()
+pub fn debug(input: &str, mode: &str) -> String {
+    let (program, repeats) = match mode {
+        "" => ("NWSE", "1000000000"),
+        _ => mode.split_once('*').unwrap_or((mode, "1")),
+    };
+    let mut platform = Platform::parse(input);
+    platform.run(&Tilt::parse_program(program), repeats.parse().unwrap());
+    let loads = [Tilt::North, Tilt::West, Tilt::South, Tilt::East].map(|edge| platform.load(edge));
+    format!(
+        "{platform}load north {} west {} south {} east {}\n",
+        loads[0], loads[1], loads[2], loads[3]
+    )
 }
 
 pub fn part1(input: &str) -> u64 {
-    let mut grid: Vec<Vec<_>> = input
-        .lines()
-        .map(|line| line.chars().map(Rock::parse).collect())
-        .collect();
-
-    tilt(&mut grid, move_north, false);
-    compute_load(&grid)
+    let mut platform = Platform::parse(input);
+    platform.run(&[Tilt::North], 1);
+    platform.load(Tilt::North)
 }
 
 pub fn part2(input: &str) -> u64 {
-    let mut grid: Vec<Vec<_>> = input
-        .lines()
-        .map(|line| line.chars().map(Rock::parse).collect())
-        .collect();
-
-    let mut map = BiMap::new();
-    map.insert(grid.clone(), 0);
-    let (cycle_start, cycle_end) = loop {
-        do_cycle(&mut grid);
-        if let Some(start) = map.get_by_left(&grid) {
-            break (*start, map.len());
+    let mut platform = Platform::parse(input);
+    platform.run(
+        &[Tilt::North, Tilt::West, Tilt::South, Tilt::East],
+        1_000_000_000,
+    );
+    platform.load(Tilt::North)
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    use super::*;
+
+    const EXAMPLE: &str = "O....#....\nO.OO#....#\n.....##...\nOO.#O....O\n.O.....O#.\nO.#..O.#.#\n..O..#O..O\n.......O..\n#....###..\n#OO..#....";
+
+    #[test]
+    fn example() {
+        assert_eq!(part1(EXAMPLE), 136);
+        assert_eq!(part2(EXAMPLE), 64);
+    }
+
+    #[test]
+    fn display_after_one_cycle() {
+        let mut platform = Platform::parse(EXAMPLE);
+        platform.run(&Tilt::parse_program("NWSE"), 1);
+        let expected = ".....#....\n....#...O#\n...OO##...\n.OO#......\n.....OOO#.\n.O#...O#.#\n....O#....\n......OOOO\n#...O###..\n#..OO#....\n";
+        assert_eq!(platform.to_string(), expected);
+    }
+
+    fn naive_tilt(grid: &mut [Vec<u8>], tilt: Tilt) {
+        let (rows, cols) = (grid.len() as isize, grid[0].len() as isize);
+        let (dr, dc) = match tilt {
+            Tilt::North => (-1, 0),
+            Tilt::South => (1, 0),
+            Tilt::West => (0, -1),
+            Tilt::East => (0, 1),
+        };
+        let mut moved = true;
+        while moved {
+            moved = false;
+            for r in 0..rows {
+                for c in 0..cols {
+                    let (nr, nc) = (r + dr, c + dc);
+                    if grid[r as usize][c as usize] == b'O'
+                        && (0..rows).contains(&nr)
+                        && (0..cols).contains(&nc)
+                        && grid[nr as usize][nc as usize] == b'.'
+                    {
+                        grid[r as usize][c as usize] = b'.';
+                        grid[nr as usize][nc as usize] = b'O';
+                        moved = true;
+                    }
+                }
+            }
         }
-        map.insert(grid.clone(), map.len());
-    };
+    }
+
+    #[test]
+    fn large_platforms_match_naive_tilts() {
+        let mut rng = StdRng::seed_from_u64(14);
+        for (rows, cols) in [(150, 3), (3, 200), (130, 257)] {
+            let mut grid: Vec<Vec<u8>> = (0..rows)
+                .map(|_| {
+                    (0..cols)
+                        .map(|_| match rng.gen_range(0..10) {
+                            0..=2 => b'O',
+                            3 => b'#',
+                            _ => b'.',
+                        })
+                        .collect()
+                })
+                .collect();
+            let input: Vec<_> = grid
+                .iter()
+                .map(|row| String::from_utf8_lossy(row))
+                .collect();
+            let mut platform = Platform::parse(&input.join("\n"));
+            for tilt in Tilt::parse_program("NWSEESWN") {
+                platform.tilt(tilt);
+                naive_tilt(&mut grid, tilt);
+                let expected: String = grid
+                    .iter()
+                    .map(|row| String::from_utf8_lossy(row) + "\n")
+                    .collect();
+                assert_eq!(platform.to_string(), expected);
+                assert_eq!(
+                    platform.rounded_by_row,
+                    transpose(&platform.rounded_by_col, rows)
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn programs_and_loads() {
+        let mut platform = Platform::parse(EXAMPLE);
+        let rocks = platform.to_string().matches('O').count() as u64;
+        platform.run(&Tilt::parse_program("WN\n"), 1_000_000_000_000);
+        let mut once = Platform::parse(EXAMPLE);
+        once.run(&Tilt::parse_program("WN"), 100);
+        assert_eq!(platform.to_string(), once.to_string());
 
-    let cycle_len = cycle_end - cycle_start;
-    let cycle_pos = (1_000_000_000 - cycle_end) % cycle_len;
-    let index = cycle_start + cycle_pos;
-    let grid = map.get_by_right(&index).unwrap();
-    compute_load(grid)
+        let (rows, cols) = (platform.rows as u64, platform.cols as u64);
+        assert_eq!(
+            platform.load(Tilt::North) + platform.load(Tilt::South),
+            rocks * (rows + 1)
+        );
+        assert_eq!(
+            platform.load(Tilt::West) + platform.load(Tilt::East),
+            rocks * (cols + 1)
+        );
+
+        let mut west = Platform::parse("..O\n.O.\nO#O");
+        west.run(&[Tilt::West], 1);
+        assert_eq!(west.to_string(), "O..\nO..\nO#O\n");
+        assert_eq!(west.load(Tilt::West), 3 + 3 + 3 + 1);
+        assert_eq!(west.load(Tilt::East), 1 + 1 + 1 + 3);
+    }
+
+    #[test]
+    fn debug_runs_programs() {
+        let out = debug("O.\n.#", "E*3");
+        assert_eq!(out, ".O\n.#\nload north 2 west 1 south 1 east 2\n");
+        assert!(debug(EXAMPLE, "").contains("load north 64 "));
+    }
+
+    #[test]
+    #[should_panic]
+    fn invalid_program() {
+        Tilt::parse_program("NX");
+    }
 }
@@ -1,3 +1,6 @@
+use std::fmt::{self, Display, Write};
+use std::hash::Hasher;
+
 enum Operation {
     Remove,
     Insert(u64),
@@ -8,80 +11,280 @@ struct Step<'a> {
     operation: Operation,
 }
 
+#[derive(Debug)]
+enum StepError {
+    InvalidLabel(String),
+    InvalidFocalLength(String),
+    MissingOperation(String),
+}
+
+impl Display for StepError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StepError::InvalidLabel(step) => write!(f, "invalid label in step {step:?}"),
+            StepError::InvalidFocalLength(step) => {
+                write!(f, "invalid focal length in step {step:?}")
+            }
+            StepError::MissingOperation(step) => write!(f, "missing `=` or `-` in step {step:?}"),
+        }
+    }
+}
+
 impl<'a> Step<'a> {
-    fn parse(s: &'a str) -> Self {
-        let (label, operation) = if s.contains('=') {
-            let (label, focal_length) = s.split_once('=').unwrap();
-            (label, Operation::Insert(focal_length.parse().unwrap()))
+    fn parse(s: &'a str) -> Result<Self, StepError> {
+        let (label, operation) = if let Some(label) = s.strip_suffix('-') {
+            (label, Operation::Remove)
+        } else if let Some((label, focal_length)) = s.split_once('=') {
+            let focal_length = focal_length
+                .parse()
+                .map_err(|_| StepError::InvalidFocalLength(s.to_string()))?;
+            (label, Operation::Insert(focal_length))
         } else {
-            (&s[..s.len() - 1], Operation::Remove)
+            return Err(StepError::MissingOperation(s.to_string()));
         };
-        Step { label, operation }
+
+        if label.is_empty() || !label.bytes().all(|b| b.is_ascii_alphabetic()) {
+            return Err(StepError::InvalidLabel(s.to_string()));
+        }
+        Ok(Step { label, operation })
     }
 }
 
-#[derive(Clone)]
-struct Pair<'a> {
-    label: &'a str,
-    focal_length: u64,
+fn steps(input: &str) -> impl Iterator<Item = &str> {
+    input.trim().split(',').map(str::trim)
 }
 
-#[derive(Clone)]
-struct LenseBox<'a>(Vec<Pair<'a>>);
+#[derive(Default)]
+struct HolidayHasher(u8);
 
-impl<'a> LenseBox<'a> {
-    fn insert(&mut self, label: &'a str, focal_length: u64) {
-        if let Some(index) = self.0.iter().position(|p| p.label == label) {
-            self.0[index].focal_length = focal_length;
-        } else {
-            self.0.push(Pair {
-                label,
-                focal_length,
-            });
+impl Hasher for HolidayHasher {
+    fn write(&mut self, bytes: &[u8]) {
+        for b in bytes {
+            self.0 = self.0.wrapping_add(*b).wrapping_mul(17);
         }
     }
 
-    fn remove(&mut self, label: &str) {
-        if let Some(index) = self.0.iter().position(|p| p.label == label) {
-            self.0.remove(index);
+    fn finish(&self) -> u64 {
+        self.0 as u64
+    }
+}
+
+#[cfg(test)]
+type HolidayHashMap<K, V> =
+    std::collections::HashMap<K, V, std::hash::BuildHasherDefault<HolidayHasher>>;
+
+fn hash(s: &str) -> u64 {
+    let mut hasher = HolidayHasher::default();
+    hasher.write(s.as_bytes());
+    hasher.finish()
+}
+
+struct HolidayMap<K, V> {
+    boxes: Vec<Vec<(K, V)>>,
+}
+
+impl<K, V> HolidayMap<K, V>
+where
+    K: AsRef<str> + Eq,
+{
+    fn new() -> Self {
+        HolidayMap {
+            boxes: (0..256).map(|_| Vec::new()).collect(),
+        }
+    }
+
+    fn slot(&mut self, key: &K) -> &mut Vec<(K, V)> {
+        &mut self.boxes[hash(key.as_ref()) as usize]
+    }
+
+    fn insert(&mut self, key: K, value: V) -> Option<V> {
+        let slot = self.slot(&key);
+        if let Some((_, old)) = slot.iter_mut().find(|(k, _)| *k == key) {
+            Some(std::mem::replace(old, value))
+        } else {
+            slot.push((key, value));
+            None
         }
     }
 
-    fn focusing_power(&self, index: u64) -> u64 {
-        self.0
+    fn remove(&mut self, key: &K) -> Option<V> {
+        let slot = self.slot(key);
+        let index = slot.iter().position(|(k, _)| k == key)?;
+        Some(slot.remove(index).1)
+    }
+
+    #[cfg(test)]
+    fn get(&self, key: &K) -> Option<&V> {
+        self.boxes[hash(key.as_ref()) as usize]
             .iter()
-            .zip(1..)
-            .map(|(pair, slot)| index * slot * pair.focal_length)
-            .sum()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v)
+    }
+
+    fn iter(&self) -> impl Iterator<Item = (usize, usize, &K, &V)> {
+        self.boxes.iter().enumerate().flat_map(|(index, lenses)| {
+            lenses
+                .iter()
+                .enumerate()
+                .map(move |(slot, (k, v))| (index, slot, k, v))
+        })
     }
 }
 
-fn hash(s: &str) -> u64 {
-    let mut x = 0_u8;
-    for c in s.as_bytes() {
-        x = x.wrapping_add(*c).wrapping_mul(17);
+impl<K, V> Display for HolidayMap<K, V>
+where
+    K: AsRef<str>,
+    V: Display,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (index, lenses) in self.boxes.iter().enumerate() {
+            if lenses.is_empty() {
+                continue;
+            }
+            write!(f, "Box {index}:")?;
+            for (k, v) in lenses {
+                write!(f, " [{} {v}]", k.as_ref())?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
     }
-    x as u64
 }
 
-pub fn part1(input: &str) -> u64 {
-    input.split(',').map(hash).sum()
+fn apply<'a>(boxes: &mut HolidayMap<&'a str, u64>, Step { label, operation }: Step<'a>) {
+    match operation {
+        Operation::Remove => {
+            boxes.remove(&label);
+        }
+        Operation::Insert(focal_length) => {
+            boxes.insert(label, focal_length);
+        }
+    }
 }
 
-pub fn part2(input: &str) -> u64 {
-    let mut boxes = vec![LenseBox(Vec::new()); 256];
+fn dump_steps(input: &str) -> Result<String, StepError> {
+    let mut boxes = HolidayMap::new();
+    let mut out = String::new();
+    for step in steps(input) {
+        apply(&mut boxes, Step::parse(step)?);
+        writeln!(out, "After \"{step}\":\n{boxes}").unwrap();
+    }
+    Ok(out)
+}
 
-    for Step { label, operation } in input.split(',').map(Step::parse) {
-        let index = hash(label) as usize;
-        match operation {
-            Operation::Remove => boxes[index].remove(label),
-            Operation::Insert(focal_length) => boxes[index].insert(label, focal_length),
+fn trace(input: &str) -> Result<String, StepError> {
+    let mut boxes = HolidayMap::new();
+    let mut out = String::new();
+    for step in steps(input) {
+        let parsed = Step::parse(step)?;
+        let (label, index) = (parsed.label, hash(parsed.label) as usize);
+        apply(&mut boxes, parsed);
+        write!(
+            out,
+            "{step:<12} label {label:<8} hash {:>3}  box {index:>3}:",
+            hash(step)
+        )
+        .unwrap();
+        for (k, v) in &boxes.boxes[index] {
+            write!(out, " [{k} {v}]").unwrap();
         }
+        out.push('\n');
+    }
+    Ok(out)
+}
+
+pub fn debug(input: &str, mode: &str) -> String {
+    let out = match mode {
+        "" | "trace" => trace(input),
+        "dump" => dump_steps(input),
+        _ => panic!("unknown day15 debug mode {mode:?}"),
+    };
+    out.unwrap_or_else(|err| format!("{err}\n"))
+}
+
+pub fn part1(input: &str) -> u64 {
+    steps(input).map(hash).sum()
+}
+
+pub fn part2(input: &str) -> u64 {
+    let mut boxes = HolidayMap::new();
+    for step in steps(input) {
+        apply(&mut boxes, Step::parse(step).unwrap());
     }
 
     boxes
         .iter()
-        .zip(1..)
-        .map(|(b, index)| b.focusing_power(index))
+        .map(|(index, slot, _, focal_length)| (index as u64 + 1) * (slot as u64 + 1) * focal_length)
         .sum()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXAMPLE: &str = "rn=1,cm-,qp=3,cm=2,qp-,pc=4,ot=9,ab=5,pc-,pc=6,ot=7";
+
+    #[test]
+    fn example() {
+        assert_eq!(hash("HASH"), 52);
+        assert_eq!(part1(EXAMPLE), 1320);
+        assert_eq!(part2(EXAMPLE), 145);
+    }
+
+    #[test]
+    fn holiday_map() {
+        let mut map = HolidayMap::new();
+        assert_eq!(map.insert("rn", 1), None);
+        assert_eq!(map.insert("cm", 2), None);
+        assert_eq!(map.insert("rn", 3), Some(1));
+        assert_eq!(map.get(&"rn"), Some(&3));
+        assert_eq!(map.remove(&"cm"), Some(2));
+        assert_eq!(map.remove(&"cm"), None);
+        assert_eq!(map.get(&"cm"), None);
+        assert_eq!(map.to_string(), "Box 0: [rn 3]\n");
+    }
+
+    #[test]
+    fn holiday_hash_map() {
+        let mut map = HolidayHashMap::default();
+        map.insert("qp".to_string(), 3);
+        map.insert("pc".to_string(), 4);
+        assert_eq!(map["qp"], 3);
+        assert_eq!(map.len(), 2);
+    }
+
+    #[test]
+    fn dump_steps_shows_boxes_after_each_step() {
+        let expected = "After \"rn=1\":\nBox 0: [rn 1]\n\nAfter \"cm-\":\nBox 0: [rn 1]\n\nAfter \"qp=3\":\nBox 0: [rn 1]\nBox 1: [qp 3]\n\n";
+        assert_eq!(dump_steps("rn=1,cm-,qp=3").unwrap(), expected);
+    }
+
+    #[test]
+    fn trace_lists_each_step() {
+        let expected = "rn=1         label rn       hash  30  box   0: [rn 1]\ncm-          label cm       hash 253  box   0: [rn 1]\nqp=3         label qp       hash  97  box   1: [qp 3]\n";
+        assert_eq!(trace("rn=1,cm-,qp=3\n").unwrap(), expected);
+    }
+
+    #[test]
+    fn debug_modes() {
+        assert_eq!(debug("rn=1", ""), trace("rn=1").unwrap());
+        assert_eq!(debug("rn=1", "dump"), dump_steps("rn=1").unwrap());
+        assert_eq!(
+            debug("rn=x", "trace"),
+            "invalid focal length in step \"rn=x\"\n"
+        );
+    }
+
+    #[test]
+    fn invalid_steps() {
+        let err = trace("rn=1,cm").unwrap_err();
+        assert_eq!(err.to_string(), "missing `=` or `-` in step \"cm\"");
+        assert!(matches!(
+            trace("rn=x"),
+            Err(StepError::InvalidFocalLength(_))
+        ));
+        assert!(matches!(trace("=1"), Err(StepError::InvalidLabel(_))));
+        assert!(matches!(trace("r1-"), Err(StepError::InvalidLabel(_))));
+        assert_eq!(part1("rn=1,\ncm-\n"), hash("rn=1") + hash("cm-"));
+    }
+}
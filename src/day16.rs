@@ -1,73 +1,9 @@
-use std::collections::HashSet;
-
-#[derive(Clone, Copy)]
-enum Tile {
-    Empty,
-    UpMirror,
-    DownMirror,
-    VerticalSplitter,
-    HorizontalSplitter,
-}
-
-impl Tile {
-    fn parse(c: char) -> Self {
-        match c {
-            '.' => Tile::Empty,
-            '/' => Tile::UpMirror,
-            '\\' => Tile::DownMirror,
-            '|' => Tile::VerticalSplitter,
-            '-' => Tile::HorizontalSplitter,
-            _ => panic!(),
-        }
-    }
-
-    fn handle(
-        self,
-        Ray { row, col, dir }: Ray,
-        rows: usize,
-        cols: usize,
-    ) -> impl Iterator<Item = Ray> {
-        use Direction::*;
-        use Tile::*;
-
-        macro_rules! ray {
-            ($row:expr, $col:expr, $dir:expr) => {
-                [Some(Ray::new($row, $col, $dir)), None]
-            };
-        }
-
-        let rays = match (self, dir) {
-            (Empty, Up) if row > 0 => ray!(row - 1, col, Up),
-            (Empty, Down) if row + 1 < rows => ray!(row + 1, col, Down),
-            (Empty, Left) if col > 0 => ray!(row, col - 1, Left),
-            (Empty, Right) if col + 1 < cols => ray!(row, col + 1, Right),
-            (UpMirror, Up) if col + 1 < cols => ray!(row, col + 1, Right),
-            (UpMirror, Down) if col > 0 => ray!(row, col - 1, Left),
-            (UpMirror, Left) if row + 1 < rows => ray!(row + 1, col, Down),
-            (UpMirror, Right) if row > 0 => ray!(row - 1, col, Up),
-            (DownMirror, Up) if col > 0 => ray!(row, col - 1, Left),
-            (DownMirror, Down) if col + 1 < cols => ray!(row, col + 1, Right),
-            (DownMirror, Left) if row > 0 => ray!(row - 1, col, Up),
-            (DownMirror, Right) if row + 1 < rows => ray!(row + 1, col, Down),
-            (VerticalSplitter, Up) if row > 0 => ray!(row - 1, col, Up),
-            (VerticalSplitter, Down) if row + 1 < rows => ray!(row + 1, col, Down),
-            (VerticalSplitter, Left | Right) => [
-                (row > 0).then(|| Ray::new(row - 1, col, Up)),
-                (row + 1 < rows).then_some(Ray::new(row + 1, col, Down)),
-            ],
-            (HorizontalSplitter, Up | Down) => [
-                (col > 0).then(|| Ray::new(row, col - 1, Left)),
-                (col + 1 < cols).then_some(Ray::new(row, col + 1, Right)),
-            ],
-            (HorizontalSplitter, Left) if col > 0 => ray!(row, col - 1, Left),
-            (HorizontalSplitter, Right) if col + 1 < cols => ray!(row, col + 1, Right),
-            _ => [None, None],
-        };
-        rays.into_iter().flatten()
-    }
-}
+use std::collections::{HashMap, HashSet};
+use std::fmt::{self, Display};
 
-#[derive(Clone, Copy, Hash, PartialEq, Eq)]
+use crate::shared::BitSet;
+
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
 enum Direction {
     Up,
     Down,
@@ -75,6 +11,185 @@ enum Direction {
     Right,
 }
 
+impl Direction {
+    fn step(self, row: usize, col: usize, rows: usize, cols: usize) -> Option<(usize, usize)> {
+        match self {
+            Direction::Up => Some((row.checked_sub(1)?, col)),
+            Direction::Down => (row + 1 < rows).then_some((row + 1, col)),
+            Direction::Left => Some((row, col.checked_sub(1)?)),
+            Direction::Right => (col + 1 < cols).then_some((row, col + 1)),
+        }
+    }
+
+    fn arrow(self) -> char {
+        match self {
+            Direction::Up => '^',
+            Direction::Down => 'v',
+            Direction::Left => '<',
+            Direction::Right => '>',
+        }
+    }
+}
+
+trait Optic {
+    fn symbol(&self) -> char;
+    fn outputs(&self, dir: Direction) -> Vec<Direction>;
+}
+
+struct Empty;
+
+impl Optic for Empty {
+    fn symbol(&self) -> char {
+        '.'
+    }
+
+    fn outputs(&self, dir: Direction) -> Vec<Direction> {
+        vec![dir]
+    }
+}
+
+struct UpMirror;
+
+impl Optic for UpMirror {
+    fn symbol(&self) -> char {
+        '/'
+    }
+
+    fn outputs(&self, dir: Direction) -> Vec<Direction> {
+        vec![match dir {
+            Direction::Up => Direction::Right,
+            Direction::Down => Direction::Left,
+            Direction::Left => Direction::Down,
+            Direction::Right => Direction::Up,
+        }]
+    }
+}
+
+struct DownMirror;
+
+impl Optic for DownMirror {
+    fn symbol(&self) -> char {
+        '\\'
+    }
+
+    fn outputs(&self, dir: Direction) -> Vec<Direction> {
+        vec![match dir {
+            Direction::Up => Direction::Left,
+            Direction::Down => Direction::Right,
+            Direction::Left => Direction::Up,
+            Direction::Right => Direction::Down,
+        }]
+    }
+}
+
+struct VerticalSplitter;
+
+impl Optic for VerticalSplitter {
+    fn symbol(&self) -> char {
+        '|'
+    }
+
+    fn outputs(&self, dir: Direction) -> Vec<Direction> {
+        match dir {
+            Direction::Up | Direction::Down => vec![dir],
+            Direction::Left | Direction::Right => vec![Direction::Up, Direction::Down],
+        }
+    }
+}
+
+struct HorizontalSplitter;
+
+impl Optic for HorizontalSplitter {
+    fn symbol(&self) -> char {
+        '-'
+    }
+
+    fn outputs(&self, dir: Direction) -> Vec<Direction> {
+        match dir {
+            Direction::Left | Direction::Right => vec![dir],
+            Direction::Up | Direction::Down => vec![Direction::Left, Direction::Right],
+        }
+    }
+}
+
+struct Absorber;
+
+impl Optic for Absorber {
+    fn symbol(&self) -> char {
+        'X'
+    }
+
+    fn outputs(&self, _: Direction) -> Vec<Direction> {
+        Vec::new()
+    }
+}
+
+struct OneWayGate(Direction);
+
+impl Optic for OneWayGate {
+    fn symbol(&self) -> char {
+        self.0.arrow()
+    }
+
+    fn outputs(&self, dir: Direction) -> Vec<Direction> {
+        if dir == self.0 {
+            vec![dir]
+        } else {
+            Vec::new()
+        }
+    }
+}
+
+#[cfg(test)]
+struct CustomSplitter {
+    symbol: char,
+    outputs: HashMap<Direction, Vec<Direction>>,
+}
+
+#[cfg(test)]
+impl Optic for CustomSplitter {
+    fn symbol(&self) -> char {
+        self.symbol
+    }
+
+    fn outputs(&self, dir: Direction) -> Vec<Direction> {
+        self.outputs.get(&dir).cloned().unwrap_or_default()
+    }
+}
+
+fn standard_optic(c: char) -> Option<Box<dyn Optic>> {
+    Some(match c {
+        '.' => Box::new(Empty),
+        '/' => Box::new(UpMirror),
+        '\\' => Box::new(DownMirror),
+        '|' => Box::new(VerticalSplitter),
+        '-' => Box::new(HorizontalSplitter),
+        'X' => Box::new(Absorber),
+        '^' => Box::new(OneWayGate(Direction::Up)),
+        'v' => Box::new(OneWayGate(Direction::Down)),
+        '<' => Box::new(OneWayGate(Direction::Left)),
+        '>' => Box::new(OneWayGate(Direction::Right)),
+        _ => return None,
+    })
+}
+
+#[derive(Debug)]
+struct UnknownTile {
+    tile: char,
+    row: usize,
+    col: usize,
+}
+
+impl Display for UnknownTile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "unknown tile {:?} at {}:{}",
+            self.tile, self.row, self.col
+        )
+    }
+}
+
 #[derive(Clone, Copy, Hash, PartialEq, Eq)]
 struct Ray {
     row: usize,
@@ -88,56 +203,508 @@ impl Ray {
     }
 }
 
-fn count_energized_tiles(grid: &[Vec<Tile>], ray: Ray) -> u64 {
-    let mut stack = vec![ray];
-    let mut seen = HashSet::new();
-    while let Some(ray) = stack.pop() {
-        if seen.contains(&ray) {
+struct Contraption {
+    tiles: Vec<Vec<Box<dyn Optic>>>,
+    rows: usize,
+    cols: usize,
+}
+
+impl Contraption {
+    fn parse(input: &str) -> Result<Self, UnknownTile> {
+        Contraption::parse_with(input, |_| None)
+    }
+
+    fn parse_with(
+        input: &str,
+        custom: impl Fn(char) -> Option<Box<dyn Optic>>,
+    ) -> Result<Self, UnknownTile> {
+        let tiles: Vec<Vec<_>> = input
+            .lines()
+            .enumerate()
+            .map(|(row, line)| {
+                line.chars()
+                    .enumerate()
+                    .map(|(col, tile)| {
+                        custom(tile)
+                            .or_else(|| standard_optic(tile))
+                            .ok_or(UnknownTile { tile, row, col })
+                    })
+                    .collect()
+            })
+            .collect::<Result<_, _>>()?;
+        let (rows, cols) = (tiles.len(), tiles[0].len());
+        Ok(Contraption { tiles, rows, cols })
+    }
+
+    fn outputs(&self, ray: Ray) -> Vec<Direction> {
+        self.tiles[ray.row][ray.col].outputs(ray.dir)
+    }
+
+    fn handle(&self, ray: Ray) -> impl Iterator<Item = Ray> + '_ {
+        self.outputs(ray).into_iter().filter_map(move |dir| {
+            let (row, col) = dir.step(ray.row, ray.col, self.rows, self.cols)?;
+            Some(Ray::new(row, col, dir))
+        })
+    }
+
+    fn edge_rays(&self) -> impl Iterator<Item = Ray> + '_ {
+        let horizontal_rays = (0..self.rows).flat_map(|row| {
+            [
+                Ray::new(row, 0, Direction::Right),
+                Ray::new(row, self.cols - 1, Direction::Left),
+            ]
+        });
+        let vertical_rays = (0..self.cols).flat_map(|col| {
+            [
+                Ray::new(0, col, Direction::Down),
+                Ray::new(self.rows - 1, col, Direction::Up),
+            ]
+        });
+        horizontal_rays.chain(vertical_rays)
+    }
+
+    fn animate(&self, start: Ray) -> Vec<Frame> {
+        let mut seen = HashSet::new();
+        let mut energized = vec![false; self.rows * self.cols];
+        let mut heads = vec![start];
+        let mut frames = Vec::new();
+
+        while !heads.is_empty() {
+            for ray in &heads {
+                seen.insert(*ray);
+                energized[ray.row * self.cols + ray.col] = true;
+            }
+            frames.push(Frame {
+                heads: heads.clone(),
+                energized: energized.clone(),
+            });
+            heads = heads
+                .iter()
+                .flat_map(|&ray| self.handle(ray))
+                .filter(|ray| !seen.contains(ray))
+                .collect();
+            heads.sort_unstable_by_key(|ray| (ray.row, ray.col, ray.dir as u8));
+            heads.dedup();
+        }
+
+        frames
+    }
+}
+
+struct Frame {
+    heads: Vec<Ray>,
+    energized: Vec<bool>,
+}
+
+impl Frame {
+    fn to_text(&self, contraption: &Contraption) -> String {
+        let mut out = String::new();
+        for (row, line) in contraption.tiles.iter().enumerate() {
+            for (col, optic) in line.iter().enumerate() {
+                let head = self
+                    .heads
+                    .iter()
+                    .find(|ray| (ray.row, ray.col) == (row, col));
+                out.push(match (optic.symbol(), head) {
+                    ('.', Some(ray)) => ray.dir.arrow(),
+                    ('.', None) if self.energized[row * contraption.cols + col] => '#',
+                    (symbol, _) => symbol,
+                });
+            }
+            out.push('\n');
+        }
+        out
+    }
+
+    fn to_ppm(&self, contraption: &Contraption, scale: usize) -> Vec<u8> {
+        let (width, height) = (contraption.cols * scale, contraption.rows * scale);
+        let mut out = format!("P6\n{width} {height}\n255\n").into_bytes();
+        for y in 0..height {
+            for x in 0..width {
+                let (row, col) = (y / scale, x / scale);
+                let is_head = self
+                    .heads
+                    .iter()
+                    .any(|ray| (ray.row, ray.col) == (row, col));
+                let color = if is_head {
+                    [255, 64, 64]
+                } else if contraption.tiles[row][col].symbol() != '.' {
+                    [160, 160, 160]
+                } else if self.energized[row * contraption.cols + col] {
+                    [255, 220, 64]
+                } else {
+                    [16, 16, 16]
+                };
+                out.extend(color);
+            }
+        }
+        out
+    }
+}
+
+struct Splitter {
+    tiles: Vec<usize>,
+    next: Vec<usize>,
+}
+
+struct BeamGraph<'a> {
+    contraption: &'a Contraption,
+    splitter_ids: HashMap<Ray, usize>,
+    component: Vec<usize>,
+    energized: Vec<Option<BitSet>>,
+}
+
+fn strongly_connected_components(splitters: &[Splitter]) -> Vec<Vec<usize>> {
+    let n = splitters.len();
+    let mut index = vec![None; n];
+    let mut low = vec![0; n];
+    let mut stack = Vec::new();
+    let mut on_stack = vec![false; n];
+    let mut components = Vec::new();
+    let mut counter = 0;
+
+    for root in 0..n {
+        if index[root].is_some() {
             continue;
         }
-        seen.insert(ray);
-        stack.extend(grid[ray.row][ray.col].handle(ray, grid.len(), grid[0].len()));
+        let mut calls = vec![(root, 0)];
+        while let Some(&(v, edge)) = calls.last() {
+            if edge == 0 {
+                index[v] = Some(counter);
+                low[v] = counter;
+                counter += 1;
+                stack.push(v);
+                on_stack[v] = true;
+            }
+
+            if let Some(&w) = splitters[v].next.get(edge) {
+                calls.last_mut().unwrap().1 += 1;
+                match index[w] {
+                    None => calls.push((w, 0)),
+                    Some(index) if on_stack[w] => low[v] = low[v].min(index),
+                    Some(_) => {}
+                }
+                continue;
+            }
+
+            calls.pop();
+            if let Some(&(parent, _)) = calls.last() {
+                low[parent] = low[parent].min(low[v]);
+            }
+            if Some(low[v]) == index[v] {
+                let mut component = Vec::new();
+                while let Some(w) = stack.pop() {
+                    on_stack[w] = false;
+                    component.push(w);
+                    if w == v {
+                        break;
+                    }
+                }
+                components.push(component);
+            }
+        }
     }
 
-    let coords: HashSet<_> = seen
-        .iter()
-        .map(|Ray { row, col, .. }| (*row, *col))
-        .collect();
-    coords.len() as u64
+    components
 }
 
-pub fn part1(input: &str) -> u64 {
-    let grid: Vec<Vec<_>> = input
-        .lines()
-        .map(|line| line.chars().map(Tile::parse).collect())
-        .collect();
+impl<'a> BeamGraph<'a> {
+    fn new(contraption: &'a Contraption, entries: impl Iterator<Item = Ray>) -> Self {
+        let mut splitter_ids = HashMap::new();
+        let mut splitter_rays = Vec::new();
+        for row in 0..contraption.rows {
+            for col in 0..contraption.cols {
+                for dir in [
+                    Direction::Up,
+                    Direction::Down,
+                    Direction::Left,
+                    Direction::Right,
+                ] {
+                    let ray = Ray::new(row, col, dir);
+                    if contraption.outputs(ray).len() > 1 {
+                        splitter_ids.insert(ray, splitter_rays.len());
+                        splitter_rays.push(ray);
+                    }
+                }
+            }
+        }
+
+        let mut graph = BeamGraph {
+            contraption,
+            splitter_ids,
+            component: Vec::new(),
+            energized: Vec::new(),
+        };
+
+        let splitters: Vec<_> = splitter_rays
+            .into_iter()
+            .map(|ray| {
+                let mut splitter = Splitter {
+                    tiles: vec![ray.row * contraption.cols + ray.col],
+                    next: Vec::new(),
+                };
+                for ray in contraption.handle(ray) {
+                    let (tiles, next) = graph.trace(ray);
+                    splitter.tiles.extend(tiles);
+                    splitter.next.extend(next);
+                }
+                splitter
+            })
+            .collect();
+
+        let entries: Vec<_> = entries.filter_map(|ray| graph.trace(ray).1).collect();
+        graph.condense(&splitters, entries);
+        graph
+    }
+
+    fn trace(&self, mut ray: Ray) -> (Vec<usize>, Option<usize>) {
+        let (rows, cols) = (self.contraption.rows, self.contraption.cols);
+        let mut tiles = Vec::new();
+        for _ in 0..4 * rows * cols {
+            tiles.push(ray.row * cols + ray.col);
+            if let Some(&splitter) = self.splitter_ids.get(&ray) {
+                return (tiles, Some(splitter));
+            }
+            match self.contraption.handle(ray).next() {
+                Some(next) => ray = next,
+                None => break,
+            }
+        }
+        (tiles, None)
+    }
+
+    fn condense(&mut self, splitters: &[Splitter], entries: Vec<usize>) {
+        let components = strongly_connected_components(splitters);
+        self.component = vec![0; splitters.len()];
+        for (id, component) in components.iter().enumerate() {
+            for &v in component {
+                self.component[v] = id;
+            }
+        }
+
+        let mut reachable = vec![false; components.len()];
+        let mut stack = entries;
+        while let Some(v) = stack.pop() {
+            if !std::mem::replace(&mut reachable[self.component[v]], true) {
+                let component = &components[self.component[v]];
+                stack.extend(component.iter().flat_map(|&v| &splitters[v].next));
+            }
+        }
+
+        let tile_count = self.contraption.rows * self.contraption.cols;
+        for (id, component) in components.iter().enumerate() {
+            if !reachable[id] {
+                self.energized.push(None);
+                continue;
+            }
+            let mut energized = BitSet::new(tile_count);
+            for &v in component {
+                for &tile in &splitters[v].tiles {
+                    energized.insert(tile);
+                }
+                for &w in &splitters[v].next {
+                    if self.component[w] != id {
+                        energized.union_with(self.energized[self.component[w]].as_ref().unwrap());
+                    }
+                }
+            }
+            self.energized.push(Some(energized));
+        }
+    }
 
-    count_energized_tiles(&grid, Ray::new(0, 0, Direction::Right))
+    fn count_energized_tiles(&self, ray: Ray) -> u64 {
+        let (tiles, next) = self.trace(ray);
+        let mut energized = match next {
+            Some(splitter) => self.energized[self.component[splitter]]
+                .clone()
+                .expect("ray is not an entry of this beam graph"),
+            None => BitSet::new(self.contraption.rows * self.contraption.cols),
+        };
+        for tile in tiles {
+            energized.insert(tile);
+        }
+        energized.count_ones() as u64
+    }
+}
+
+const FRAME_DIR: &str = "day16-frames";
+
+fn write_frames(contraption: &Contraption, frames: &[Frame]) -> std::io::Result<String> {
+    std::fs::create_dir_all(FRAME_DIR)?;
+    for (i, frame) in frames.iter().enumerate() {
+        std::fs::write(
+            format!("{FRAME_DIR}/frame-{i:04}.ppm"),
+            frame.to_ppm(contraption, 4),
+        )?;
+    }
+    Ok(format!("wrote {} frames to {FRAME_DIR}/\n", frames.len()))
+}
+
+pub fn debug(input: &str, mode: &str) -> String {
+    let contraption = match Contraption::parse(input) {
+        Ok(contraption) => contraption,
+        Err(err) => return format!("{err}\n"),
+    };
+    let frames = contraption.animate(Ray::new(0, 0, Direction::Right));
+    match mode {
+        "" | "text" => frames
+            .iter()
+            .map(|frame| frame.to_text(&contraption))
+            .collect::<Vec<_>>()
+            .join("\n"),
+        "ppm" => write_frames(&contraption, &frames).unwrap_or_else(|err| format!("{err}\n")),
+        _ => panic!("unknown day16 debug mode {mode:?}"),
+    }
+}
+
+pub fn part1(input: &str) -> u64 {
+    let contraption = Contraption::parse(input).unwrap();
+    let start = Ray::new(0, 0, Direction::Right);
+    BeamGraph::new(&contraption, std::iter::once(start)).count_energized_tiles(start)
 }
 
 pub fn part2(input: &str) -> u64 {
-    let grid: Vec<Vec<_>> = input
-        .lines()
-        .map(|line| line.chars().map(Tile::parse).collect())
-        .collect();
-    let (rows, cols) = (grid.len(), grid[0].len());
-
-    let horizontal_rays = (0..rows).flat_map(|row| {
-        [
-            Ray::new(row, 0, Direction::Right),
-            Ray::new(row, cols - 1, Direction::Left),
-        ]
-    });
-    let vertical_rays = (0..cols).flat_map(|col| {
-        [
-            Ray::new(0, col, Direction::Down),
-            Ray::new(rows - 1, col, Direction::Up),
-        ]
-    });
-
-    horizontal_rays
-        .chain(vertical_rays)
-        .map(|ray| count_energized_tiles(&grid, ray))
+    let contraption = Contraption::parse(input).unwrap();
+    let graph = BeamGraph::new(&contraption, contraption.edge_rays());
+    contraption
+        .edge_rays()
+        .map(|ray| graph.count_energized_tiles(ray))
         .max()
         .unwrap()
 }
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    use super::*;
+
+    const EXAMPLE: &str = r".|...\....
+|.-.\.....
+.....|-...
+........|.
+..........
+.........\
+..../.\\..
+.-.-/..|..
+.|....-|.\
+..//.|....";
+
+    #[test]
+    fn example() {
+        assert_eq!(part1(EXAMPLE), 46);
+        assert_eq!(part2(EXAMPLE), 51);
+    }
+
+    #[test]
+    fn long_splitter_chain() {
+        let cols = 20_000;
+        let input = format!("{}\n{}", r"|/".repeat(cols / 2), r"\/".repeat(cols / 2));
+        assert_eq!(part1(&input), 2 * cols as u64);
+    }
+
+    fn energized_by_bfs(contraption: &Contraption, start: Ray) -> u64 {
+        let mut seen = HashSet::from([start]);
+        let mut stack = vec![start];
+        while let Some(ray) = stack.pop() {
+            for next in contraption.handle(ray) {
+                if seen.insert(next) {
+                    stack.push(next);
+                }
+            }
+        }
+        let tiles: HashSet<_> = seen.into_iter().map(|ray| (ray.row, ray.col)).collect();
+        tiles.len() as u64
+    }
+
+    #[test]
+    fn beam_graph_matches_bfs() {
+        let mut rng = StdRng::seed_from_u64(16);
+        for _ in 0..50 {
+            let (rows, cols) = (rng.gen_range(1..12), rng.gen_range(1..12));
+            let input: Vec<String> = (0..rows)
+                .map(|_| {
+                    (0..cols)
+                        .map(|_| {
+                            let tiles =
+                                ['.', '.', '.', '/', '\\', '|', '-', 'X', '<', '>', '^', 'v'];
+                            tiles[rng.gen_range(0..tiles.len())]
+                        })
+                        .collect()
+                })
+                .collect();
+            let contraption = Contraption::parse(&input.join("\n")).unwrap();
+            let graph = BeamGraph::new(&contraption, contraption.edge_rays());
+            for ray in contraption.edge_rays() {
+                assert_eq!(
+                    graph.count_energized_tiles(ray),
+                    energized_by_bfs(&contraption, ray)
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn custom_optics() {
+        let contraption = Contraption::parse_with("...\n.+.\n...", |c| {
+            (c == '+').then(|| {
+                let outputs = [
+                    Direction::Up,
+                    Direction::Down,
+                    Direction::Left,
+                    Direction::Right,
+                ];
+                Box::new(CustomSplitter {
+                    symbol: '+',
+                    outputs: outputs.map(|dir| (dir, outputs.to_vec())).into(),
+                }) as Box<dyn Optic>
+            })
+        })
+        .unwrap();
+        let start = Ray::new(1, 0, Direction::Right);
+        let graph = BeamGraph::new(&contraption, std::iter::once(start));
+        assert_eq!(graph.count_energized_tiles(start), 5);
+
+        let err = Contraption::parse("..\n.+").err().unwrap();
+        assert_eq!(err.to_string(), "unknown tile '+' at 1:1");
+    }
+
+    #[test]
+    fn gates_and_absorbers() {
+        assert_eq!(part1(".>.<."), 4);
+        assert_eq!(part1("..X.."), 3);
+        assert_eq!(part1("|.\n^.\n.."), 2);
+    }
+
+    #[test]
+    fn animation_frames() {
+        let contraption = Contraption::parse("..\\\n...").unwrap();
+        let frames = contraption.animate(Ray::new(0, 0, Direction::Right));
+        let text: Vec<_> = frames
+            .iter()
+            .map(|frame| frame.to_text(&contraption))
+            .collect();
+        assert_eq!(
+            text,
+            [">.\\\n...\n", "#>\\\n...\n", "##\\\n...\n", "##\\\n..v\n"]
+        );
+
+        let ppm = frames[3].to_ppm(&contraption, 2);
+        let header = b"P6\n6 4\n255\n";
+        assert_eq!(&ppm[..header.len()], header);
+        let pixels = &ppm[header.len()..];
+        assert_eq!(pixels.len(), 6 * 4 * 3);
+        let pixel = |x: usize, y: usize| &pixels[(y * 6 + x) * 3..][..3];
+        assert_eq!(pixel(1, 1), [255, 220, 64]);
+        assert_eq!(pixel(4, 0), [160, 160, 160]);
+        assert_eq!(pixel(5, 3), [255, 64, 64]);
+        assert_eq!(pixel(0, 2), [16, 16, 16]);
+    }
+
+    #[test]
+    fn debug_text_frames() {
+        assert_eq!(debug(".\\\n..", ""), ">\\\n..\n\n#\\\n..\n\n#\\\n.v\n");
+        assert_eq!(debug(".+", "text"), "unknown tile '+' at 0:1\n");
+    }
+}
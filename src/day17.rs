@@ -1,5 +1,3 @@
-use std::collections::{BinaryHeap, HashMap, HashSet};
-
 #[derive(Clone, Copy, PartialEq, Eq, Hash)]
 enum Direction {
     Up,
@@ -8,185 +6,318 @@ enum Direction {
     Right,
 }
 
+impl Direction {
+    const ALL: [Direction; 4] = [
+        Direction::Up,
+        Direction::Down,
+        Direction::Left,
+        Direction::Right,
+    ];
+
+    fn opposite(self) -> Self {
+        match self {
+            Direction::Up => Direction::Down,
+            Direction::Down => Direction::Up,
+            Direction::Left => Direction::Right,
+            Direction::Right => Direction::Left,
+        }
+    }
+
+    fn step(self, row: usize, col: usize, rows: usize, cols: usize) -> Option<(usize, usize)> {
+        match self {
+            Direction::Up => Some((row.checked_sub(1)?, col)),
+            Direction::Down => (row + 1 < rows).then_some((row + 1, col)),
+            Direction::Left => Some((row, col.checked_sub(1)?)),
+            Direction::Right => (col + 1 < cols).then_some((row, col + 1)),
+        }
+    }
+}
+
 #[derive(Clone, Copy, PartialEq, Eq, Hash)]
 struct Position {
     row: usize,
     col: usize,
     dir: Direction,
-    run: u8,
+    run: usize,
 }
 
 impl Position {
-    fn new(row: usize, col: usize, dir: Direction, run: u8) -> Self {
+    fn new(row: usize, col: usize, dir: Direction, run: usize) -> Self {
         Position { row, col, dir, run }
     }
+}
 
-    fn up(self, min_run: u8, max_run: u8) -> Option<Self> {
-        if self.row == 0 || self.dir == Direction::Down {
-            None
-        } else if self.dir == Direction::Up {
-            (self.run < max_run).then_some(Position::new(
-                self.row - 1,
-                self.col,
-                Direction::Up,
-                self.run + 1,
-            ))
-        } else {
-            (self.run >= min_run).then_some(Position::new(self.row - 1, self.col, Direction::Up, 1))
-        }
-    }
-
-    fn down(self, rows: usize, min_run: u8, max_run: u8) -> Option<Self> {
-        if self.row == rows - 1 || self.dir == Direction::Up {
-            None
-        } else if self.dir == Direction::Down {
-            (self.run < max_run).then_some(Position::new(
-                self.row + 1,
-                self.col,
-                Direction::Down,
-                self.run + 1,
-            ))
-        } else {
-            (self.run >= min_run).then_some(Position::new(
-                self.row + 1,
-                self.col,
-                Direction::Down,
-                1,
-            ))
-        }
-    }
-
-    fn left(self, min_run: u8, max_run: u8) -> Option<Self> {
-        if self.col == 0 || self.dir == Direction::Right {
-            None
-        } else if self.dir == Direction::Left {
-            (self.run < max_run).then_some(Position::new(
-                self.row,
-                self.col - 1,
-                Direction::Left,
-                self.run + 1,
-            ))
-        } else {
-            (self.run >= min_run).then_some(Position::new(
-                self.row,
-                self.col - 1,
-                Direction::Left,
-                1,
-            ))
-        }
-    }
-
-    fn right(self, cols: usize, min_run: u8, max_run: u8) -> Option<Self> {
-        if self.col == cols - 1 || self.dir == Direction::Left {
-            None
-        } else if self.dir == Direction::Right {
-            (self.run < max_run).then_some(Position::new(
-                self.row,
-                self.col + 1,
-                Direction::Right,
-                self.run + 1,
-            ))
-        } else {
-            (self.run >= min_run).then_some(Position::new(
-                self.row,
-                self.col + 1,
-                Direction::Right,
-                1,
-            ))
-        }
-    }
+#[derive(Clone, Copy)]
+struct Crucible {
+    min_run: usize,
+    max_run: usize,
+}
 
+impl Crucible {
     fn neighbors(
         self,
+        position: Position,
         rows: usize,
         cols: usize,
-        min_run: u8,
-        max_run: u8,
     ) -> impl Iterator<Item = Position> {
-        self.up(min_run, max_run)
-            .into_iter()
-            .chain(self.down(rows, min_run, max_run))
-            .chain(self.left(min_run, max_run))
-            .chain(self.right(cols, min_run, max_run))
+        Direction::ALL.into_iter().filter_map(move |dir| {
+            if dir == position.dir.opposite() {
+                return None;
+            }
+            let run = if dir == position.dir {
+                (position.run < self.max_run).then_some(position.run + 1)?
+            } else {
+                (position.run >= self.min_run && self.max_run >= 1).then_some(1)?
+            };
+            let (row, col) = dir.step(position.row, position.col, rows, cols)?;
+            Some(Position::new(row, col, dir, run))
+        })
     }
 
-    fn at_end(self, rows: usize, cols: usize, min_run: u8) -> bool {
-        self.row == rows - 1 && self.col == cols - 1 && self.run >= min_run
+    fn index(self, position: Position, cols: usize) -> usize {
+        ((position.row * cols + position.col) * 4 + position.dir as usize) * (self.max_run + 1)
+            + position.run
     }
 }
 
-#[derive(Clone, Copy, PartialEq, Eq, Hash)]
-struct Pair {
+struct Route {
     heat_loss: u64,
-    position: Position,
+    steps: Vec<Position>,
 }
 
-impl PartialOrd for Pair {
-    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
-        Some(self.cmp(other))
-    }
-}
-
-impl Ord for Pair {
-    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
-        self.heat_loss.cmp(&other.heat_loss).reverse()
-    }
-}
-
-fn find_min_heat_loss(input: &str, min_run: u8, max_run: u8) -> u64 {
-    let grid: Vec<Vec<_>> = input
+fn parse(input: &str) -> Vec<Vec<u64>> {
+    input
         .lines()
         .map(|line| line.chars().map(|c| c as u64 - 48).collect())
-        .collect();
+        .collect()
+}
+
+fn find_route(
+    grid: &[Vec<u64>],
+    crucible: Crucible,
+    start: (usize, usize),
+    goal: (usize, usize),
+    use_heuristic: bool,
+) -> Option<Route> {
     let (rows, cols) = (grid.len(), grid[0].len());
+    let heuristic = |position: Position| {
+        if use_heuristic {
+            (position.row.abs_diff(goal.0) + position.col.abs_diff(goal.1)) as u64
+        } else {
+            0
+        }
+    };
+
+    let states = rows * cols * 4 * (crucible.max_run + 1);
+    let mut heat_loss = vec![u64::MAX; states];
+    let mut previous = vec![None; states];
+    let mut done = vec![false; states];
+    let mut buckets: Vec<Vec<Position>> = Vec::new();
+
+    for dir in Direction::ALL {
+        let position = Position::new(start.0, start.1, dir, 0);
+        heat_loss[crucible.index(position, cols)] = 0;
+        let priority = heuristic(position) as usize;
+        if buckets.len() <= priority {
+            buckets.resize(priority + 1, Vec::new());
+        }
+        buckets[priority].push(position);
+    }
 
-    let mut done = HashSet::new();
-    let mut min_heat_loss = HashMap::new();
-    let mut queue = BinaryHeap::from([
-        Pair {
-            heat_loss: 0,
-            position: Position::new(0, 0, Direction::Down, 0),
-        },
-        Pair {
-            heat_loss: 0,
-            position: Position::new(0, 0, Direction::Right, 0),
-        },
-    ]);
-    while let Some(Pair {
-        heat_loss,
-        position,
-    }) = queue.pop()
-    {
-        if position.at_end(rows, cols, min_run) {
-            return heat_loss;
-        } else if done.contains(&position) {
+    let mut priority = 0;
+    while priority < buckets.len() {
+        let Some(position) = buckets[priority].pop() else {
+            priority += 1;
+            continue;
+        };
+        let index = crucible.index(position, cols);
+        if done[index] {
             continue;
         }
-        done.insert(position);
-        min_heat_loss.insert(position, heat_loss);
+        done[index] = true;
 
-        for neighbor in position.neighbors(rows, cols, min_run, max_run) {
-            if done.contains(&neighbor) {
-                continue;
+        if (position.row, position.col) == goal && position.run >= crucible.min_run {
+            let mut steps = vec![position];
+            while let Some(before) = previous[crucible.index(*steps.last().unwrap(), cols)] {
+                steps.push(before);
             }
-            let new_heat_loss = heat_loss + grid[neighbor.row][neighbor.col];
-            if new_heat_loss < min_heat_loss.get(&neighbor).copied().unwrap_or(u64::MAX) {
-                min_heat_loss.insert(neighbor, new_heat_loss);
-                queue.push(Pair {
-                    heat_loss: new_heat_loss,
-                    position: neighbor,
-                });
+            steps.reverse();
+            return Some(Route {
+                heat_loss: heat_loss[index],
+                steps,
+            });
+        }
+
+        for neighbor in crucible.neighbors(position, rows, cols) {
+            let neighbor_index = crucible.index(neighbor, cols);
+            let new_heat_loss = heat_loss[index] + grid[neighbor.row][neighbor.col];
+            if !done[neighbor_index] && new_heat_loss < heat_loss[neighbor_index] {
+                heat_loss[neighbor_index] = new_heat_loss;
+                previous[neighbor_index] = Some(position);
+                let neighbor_priority = (new_heat_loss + heuristic(neighbor)) as usize;
+                if buckets.len() <= neighbor_priority {
+                    buckets.resize(neighbor_priority + 1, Vec::new());
+                }
+                buckets[neighbor_priority].push(neighbor);
             }
         }
     }
 
-    unreachable!()
+    None
+}
+
+fn render(grid: &[Vec<u64>], route: &Route) -> String {
+    let mut chars: Vec<Vec<_>> = grid
+        .iter()
+        .map(|row| {
+            row.iter()
+                .map(|&h| char::from_digit(h as u32, 10).unwrap())
+                .collect()
+        })
+        .collect();
+    for step in route.steps.iter().skip(1) {
+        chars[step.row][step.col] = match step.dir {
+            Direction::Up => '^',
+            Direction::Down => 'v',
+            Direction::Left => '<',
+            Direction::Right => '>',
+        };
+    }
+    chars
+        .into_iter()
+        .map(|row| row.into_iter().chain(['\n']).collect::<String>())
+        .collect()
+}
+
+fn best_route(grid: &[Vec<u64>], min_run: usize, max_run: usize) -> Option<Route> {
+    let goal = (grid.len() - 1, grid[0].len() - 1);
+    let crucible = Crucible { min_run, max_run };
+    find_route(grid, crucible, (0, 0), goal, true)
+}
+
+fn min_heat_loss(input: &str, min_run: usize, max_run: usize) -> u64 {
+    best_route(&parse(input), min_run, max_run)
+        .unwrap()
+        .heat_loss
+}
+
+pub fn debug(input: &str, mode: &str) -> String {
+    let crucibles: &[_] = match mode {
+        "" => &[("part1", 0, 3), ("part2", 4, 10)],
+        "part1" => &[("part1", 0, 3)],
+        "part2" => &[("part2", 4, 10)],
+        _ => panic!("unknown day17 debug mode {mode:?}"),
+    };
+    let grid = parse(input);
+    let mut out = String::new();
+    for &(name, min_run, max_run) in crucibles {
+        match best_route(&grid, min_run, max_run) {
+            Some(route) => {
+                out += &format!("{name}: heat loss {}\n", route.heat_loss);
+                out += &render(&grid, &route);
+            }
+            None => out += &format!("{name}: no route\n"),
+        }
+    }
+    out
 }
 
 pub fn part1(input: &str) -> u64 {
-    find_min_heat_loss(input, 0, 3)
+    min_heat_loss(input, 0, 3)
 }
 
 pub fn part2(input: &str) -> u64 {
-    find_min_heat_loss(input, 4, 10)
+    min_heat_loss(input, 4, 10)
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    use super::*;
+
+    const EXAMPLE: &str = "2413432311323\n3215453535623\n3255245654254\n3446585845452\n4546657867536\n1438598798454\n4457876987766\n3637877979653\n4654967986887\n4564679986453\n1224686865563\n2546548887735\n4322674655533";
+
+    #[test]
+    fn example() {
+        assert_eq!(part1(EXAMPLE), 102);
+        assert_eq!(part2(EXAMPLE), 94);
+        assert_eq!(
+            part2("111111111111\n999999999991\n999999999991\n999999999991\n999999999991"),
+            71
+        );
+    }
+
+    #[test]
+    fn render_route() {
+        let grid = parse("19\n11");
+        let crucible = Crucible {
+            min_run: 0,
+            max_run: 3,
+        };
+        let route = find_route(&grid, crucible, (0, 0), (1, 1), true).unwrap();
+        assert_eq!(route.heat_loss, 2);
+        assert_eq!(render(&grid, &route), "19\nv>\n");
+
+        let grid = parse(EXAMPLE);
+        let route = find_route(&grid, crucible, (0, 0), (12, 12), false).unwrap();
+        let rendered = render(&grid, &route);
+        assert!(rendered.starts_with("2>>34^>>>1323\n32v>>>35v5623\n"));
+        let steps = rendered.chars().filter(|c| "^v<>".contains(*c)).count();
+        assert_eq!(steps, route.steps.len() - 1);
+    }
+
+    #[test]
+    fn debug_renders_routes() {
+        assert_eq!(debug("19\n11", "part1"), "part1: heat loss 2\n19\nv>\n");
+        assert_eq!(debug("19\n11", "part2"), "part2: no route\n");
+        let out = debug(EXAMPLE, "");
+        assert!(out.starts_with("part1: heat loss 102\n"));
+        assert!(out.contains("part2: heat loss 94\n"));
+    }
+
+    #[test]
+    fn degenerate_crucibles() {
+        let grid = parse("19\n11");
+        for (min_run, max_run) in [(0, 0), (2, 0), (3, 1)] {
+            let crucible = Crucible { min_run, max_run };
+            assert!(find_route(&grid, crucible, (0, 0), (1, 1), true).is_none());
+        }
+        let crucible = Crucible {
+            min_run: 0,
+            max_run: 0,
+        };
+        let route = find_route(&grid, crucible, (0, 0), (0, 0), true).unwrap();
+        assert_eq!(route.heat_loss, 0);
+        let crucible = Crucible {
+            min_run: 1,
+            max_run: 1,
+        };
+        let route = find_route(&grid, crucible, (0, 0), (1, 1), true).unwrap();
+        assert_eq!(route.heat_loss, 2);
+    }
+
+    #[test]
+    fn heuristic_does_not_change_heat_loss() {
+        let mut rng = StdRng::seed_from_u64(17);
+        for _ in 0..200 {
+            let (rows, cols) = (rng.gen_range(1..8), rng.gen_range(1..8));
+            let grid: Vec<Vec<u64>> = (0..rows)
+                .map(|_| (0..cols).map(|_| rng.gen_range(1..10)).collect())
+                .collect();
+            let min_run = rng.gen_range(0..4);
+            let crucible = Crucible {
+                min_run,
+                max_run: rng.gen_range(min_run.max(1)..6),
+            };
+            let goal = (rows - 1, cols - 1);
+            let with = find_route(&grid, crucible, (0, 0), goal, true);
+            let without = find_route(&grid, crucible, (0, 0), goal, false);
+            assert_eq!(
+                with.map(|route| route.heat_loss),
+                without.map(|route| route.heat_loss)
+            );
+        }
+    }
 }
@@ -2,15 +2,18 @@
 struct Dig {
     dir: Direction,
     len: i64,
+    color: Option<u32>,
 }
 
 impl Dig {
     fn part1(line: &str) -> Self {
         let (dir, rest) = line.split_once(' ').unwrap();
-        let (len, _) = rest.split_once(' ').unwrap();
+        let (len, color) = rest.split_once(' ').unwrap();
+        let color = color.trim_start_matches("(#").trim_end_matches(')');
         Dig {
             dir: Direction::parse(dir),
             len: len.parse().unwrap(),
+            color: u32::from_str_radix(color, 16).ok(),
         }
     }
 
@@ -19,11 +22,12 @@ impl Dig {
         Dig {
             dir: Direction::parse(&rest[5..6]),
             len: i64::from_str_radix(&rest[..5], 16).unwrap(),
+            color: None,
         }
     }
 }
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 enum Direction {
     Right,
     Down,
@@ -42,6 +46,15 @@ impl Direction {
         }
     }
 
+    fn opposite(self) -> Self {
+        match self {
+            Direction::Right => Direction::Left,
+            Direction::Down => Direction::Up,
+            Direction::Left => Direction::Right,
+            Direction::Up => Direction::Down,
+        }
+    }
+
     fn step(self, (x, y): (i64, i64), len: i64) -> (i64, i64) {
         match self {
             Direction::Up => (x, y + len),
@@ -52,41 +65,263 @@ impl Direction {
     }
 }
 
-fn get_area(digs: impl Iterator<Item = Dig> + Clone) -> u64 {
-    let mut pos = (0, 0);
-    let mut vertices = vec![pos];
-    let mut edge = 0;
+#[derive(Debug)]
+enum LagoonError {
+    Empty,
+    InvalidDig(usize),
+    NotClosed((i64, i64)),
+    DoublesBack(usize, usize),
+    SelfIntersecting(usize, usize),
+}
 
-    let mut fast = digs.clone();
-    let first = fast.next().unwrap();
+impl std::fmt::Display for LagoonError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LagoonError::Empty => write!(f, "dig plan is empty"),
+            LagoonError::InvalidDig(i) => write!(f, "dig {i} does not have a positive length"),
+            LagoonError::NotClosed((x, y)) => {
+                write!(f, "dig plan ends at ({x}, {y}), not at the start")
+            }
+            LagoonError::DoublesBack(i, j) => write!(f, "dig {j} doubles back along dig {i}"),
+            LagoonError::SelfIntersecting(i, j) => write!(f, "digs {i} and {j} cross"),
+        }
+    }
+}
 
-    for (cur, next) in digs.clone().zip(fast.chain(Some(first))) {
-        pos = cur.dir.step(pos, cur.len);
-        vertices.push(pos);
-        match (cur.dir, next.dir) {
-            (Direction::Down, Direction::Left) => edge += 1,
-            (Direction::Down, Direction::Right) | (Direction::Right, _) => {}
-            (Direction::Up, _) | (Direction::Left, Direction::Up) => edge += cur.len,
-            (Direction::Left, Direction::Down) => edge += cur.len - 1,
-            _ => panic!(),
+fn segments_touch(a: ((i64, i64), (i64, i64)), b: ((i64, i64), (i64, i64))) -> bool {
+    let ((ax1, ay1), (ax2, ay2)) = a;
+    let ((bx1, by1), (bx2, by2)) = b;
+    ax1.min(ax2) <= bx1.max(bx2)
+        && bx1.min(bx2) <= ax1.max(ax2)
+        && ay1.min(ay2) <= by1.max(by2)
+        && by1.min(by2) <= ay1.max(ay2)
+}
+
+fn trace(digs: &[Dig]) -> Result<Vec<(i64, i64)>, LagoonError> {
+    if digs.is_empty() {
+        return Err(LagoonError::Empty);
+    }
+    if let Some(i) = digs.iter().position(|dig| dig.len <= 0) {
+        return Err(LagoonError::InvalidDig(i));
+    }
+
+    let mut vertices = vec![(0, 0)];
+    for dig in digs {
+        vertices.push(dig.dir.step(*vertices.last().unwrap(), dig.len));
+    }
+    let end = vertices.pop().unwrap();
+    if end != (0, 0) {
+        return Err(LagoonError::NotClosed(end));
+    }
+
+    let n = digs.len();
+    let segment = |i: usize| (vertices[i], vertices[(i + 1) % n]);
+    for i in 0..n {
+        let next = (i + 1) % n;
+        if digs[next].dir == digs[i].dir.opposite() {
+            return Err(LagoonError::DoublesBack(i, next));
+        }
+        for j in i + 2..n {
+            if (j + 1) % n != i && segments_touch(segment(i), segment(j)) {
+                return Err(LagoonError::SelfIntersecting(i, j));
+            }
         }
     }
 
-    let area = vertices
-        .iter()
-        .zip(vertices.iter().skip(1))
+    Ok(vertices)
+}
+
+fn get_area(digs: impl Iterator<Item = Dig>) -> Result<u64, LagoonError> {
+    let vertices = trace(&digs.collect::<Vec<_>>())?;
+    let edges = vertices.iter().zip(vertices.iter().cycle().skip(1));
+
+    let double_area = edges
+        .clone()
         .map(|((x1, y1), (x2, y2))| x1 * y2 - x2 * y1)
         .sum::<i64>()
-        .abs()
-        / 2;
+        .unsigned_abs();
+    let boundary: u64 = edges
+        .map(|((x1, y1), (x2, y2))| x1.abs_diff(*x2) + y1.abs_diff(*y2))
+        .sum();
+
+    Ok((double_area + boundary) / 2 + 1)
+}
 
-    (area + edge) as u64
+fn bounds(vertices: &[(i64, i64)]) -> ((i64, i64), (i64, i64)) {
+    let xs = vertices.iter().map(|v| v.0);
+    let ys = vertices.iter().map(|v| v.1);
+    (
+        (xs.clone().min().unwrap(), ys.clone().min().unwrap()),
+        (xs.max().unwrap(), ys.max().unwrap()),
+    )
+}
+
+fn to_svg(digs: &[Dig]) -> Result<String, LagoonError> {
+    let vertices = trace(digs)?;
+    let ((min_x, min_y), (max_x, max_y)) = bounds(&vertices);
+    let (width, height) = (max_x - min_x + 1, max_y - min_y + 1);
+    let stroke = (width.max(height) as f64 / 200.0).max(0.2);
+
+    let mut svg = format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" viewBox=\"{} {} {width} {height}\">\n",
+        min_x as f64 - 0.5,
+        -max_y as f64 - 0.5,
+    );
+    let points: Vec<_> = vertices
+        .iter()
+        .map(|(x, y)| format!("{x},{}", -y))
+        .collect();
+    svg += &format!(
+        "<polygon points=\"{}\" fill=\"#eeeeee\"/>\n",
+        points.join(" ")
+    );
+    for (i, dig) in digs.iter().enumerate() {
+        let ((x1, y1), (x2, y2)) = (vertices[i], vertices[(i + 1) % vertices.len()]);
+        let color = dig
+            .color
+            .map_or("#000000".to_string(), |c| format!("#{c:06x}"));
+        svg += &format!(
+            "<line x1=\"{x1}\" y1=\"{}\" x2=\"{x2}\" y2=\"{}\" stroke=\"{color}\" stroke-width=\"{stroke}\" stroke-linecap=\"square\"/>\n",
+            -y1, -y2
+        );
+    }
+    svg += "</svg>\n";
+    Ok(svg)
+}
+
+fn is_dug(vertices: &[(i64, i64)], (x, y): (i64, i64)) -> bool {
+    let edges = vertices.iter().zip(vertices.iter().cycle().skip(1));
+    let mut crossings = 0;
+    for (&(x1, y1), &(x2, y2)) in edges {
+        if (x1.min(x2)..=x1.max(x2)).contains(&x) && (y1.min(y2)..=y1.max(y2)).contains(&y) {
+            return true;
+        }
+        if x1 == x2 && x1 > x && (y1.min(y2)..y1.max(y2)).contains(&y) {
+            crossings += 1;
+        }
+    }
+    crossings % 2 == 1
+}
+
+fn to_ascii(digs: &[Dig], max_size: i64) -> Result<String, LagoonError> {
+    let vertices = trace(digs)?;
+    let ((min_x, min_y), (max_x, max_y)) = bounds(&vertices);
+    let extent = (max_x - min_x + 1).max(max_y - min_y + 1);
+    let scale = (extent + max_size - 1) / max_size;
+
+    let mut out = String::new();
+    let mut y = max_y;
+    while y >= min_y {
+        let mut x = min_x;
+        while x <= max_x {
+            out.push(if is_dug(&vertices, (x, y)) { '#' } else { '.' });
+            x += scale;
+        }
+        out.push('\n');
+        y -= scale;
+    }
+    Ok(out)
+}
+
+pub fn debug(input: &str, mode: &str) -> String {
+    let digs: Vec<_> = input.lines().map(Dig::part1).collect();
+    let out = match mode {
+        "" | "ascii" => to_ascii(&digs, 60),
+        "svg" => to_svg(&digs),
+        _ => panic!("unknown day18 debug mode {mode:?}"),
+    };
+    out.unwrap_or_else(|err| format!("{err}\n"))
 }
 
 pub fn part1(input: &str) -> u64 {
-    get_area(input.lines().map(Dig::part1))
+    get_area(input.lines().map(Dig::part1)).unwrap()
 }
 
 pub fn part2(input: &str) -> u64 {
-    get_area(input.lines().map(Dig::part2))
+    get_area(input.lines().map(Dig::part2)).unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXAMPLE: &str = "R 6 (#70c710)\nD 5 (#0dc571)\nL 2 (#5713f0)\nD 2 (#d2c081)\nR 2 (#59c680)\nD 2 (#411b91)\nL 5 (#8ceee2)\nU 2 (#caa173)\nL 1 (#1b58a2)\nU 2 (#caa171)\nR 2 (#7807d2)\nU 3 (#a77fa3)\nL 2 (#015232)\nU 2 (#7a21e3)";
+
+    fn digs() -> Vec<Dig> {
+        EXAMPLE.lines().map(Dig::part1).collect()
+    }
+
+    #[test]
+    fn example() {
+        assert_eq!(part1(EXAMPLE), 62);
+        assert_eq!(part2(EXAMPLE), 952408144115);
+    }
+
+    #[test]
+    fn ascii() {
+        let expected = "#######\n#######\n#######\n..#####\n..#####\n#######\n#####..\n#######\n.######\n.######\n";
+        assert_eq!(to_ascii(&digs(), 10).unwrap(), expected);
+        assert_eq!(
+            to_ascii(&digs(), 5).unwrap(),
+            "####\n####\n.###\n###.\n.###\n"
+        );
+    }
+
+    #[test]
+    fn svg() {
+        let svg = to_svg(&digs()).unwrap();
+        assert!(svg.starts_with(
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" viewBox=\"-0.5 -0.5 7 10\">\n<polygon points=\"0,0 6,0 6,5 "
+        ));
+        assert_eq!(svg.matches("<line ").count(), 14);
+        assert!(svg.contains("<line x1=\"0\" y1=\"0\" x2=\"6\" y2=\"0\" stroke=\"#70c710\""));
+        assert!(svg.ends_with("</svg>\n"));
+    }
+
+    #[test]
+    fn debug_modes() {
+        assert_eq!(debug(EXAMPLE, ""), to_ascii(&digs(), 60).unwrap());
+        assert_eq!(debug(EXAMPLE, "svg"), to_svg(&digs()).unwrap());
+        assert_eq!(
+            debug("R 2 (#000000)", "ascii"),
+            "dig plan ends at (2, 0), not at the start\n"
+        );
+    }
+
+    #[test]
+    fn invalid_plans() {
+        let dig = |line| Dig::part1(line);
+        assert!(matches!(trace(&[]), Err(LagoonError::Empty)));
+        assert!(matches!(
+            to_svg(&[dig("R 2 (#000000)"), dig("D 1 (#000000)")]),
+            Err(LagoonError::NotClosed((2, -1)))
+        ));
+        let crossing = ["R 2", "D 2", "L 1", "U 3", "L 1", "D 1"]
+            .map(|s| Dig::part1(&format!("{s} (#000000)")));
+        assert!(matches!(
+            to_ascii(&crossing, 10),
+            Err(LagoonError::SelfIntersecting(_, _))
+        ));
+
+        let plan = |digs: &[&str]| {
+            let digs: Vec<_> = digs
+                .iter()
+                .map(|s| Dig::part1(&format!("{s} (#000000)")))
+                .collect();
+            trace(&digs).unwrap_err().to_string()
+        };
+        assert_eq!(
+            plan(&["R 0", "L 0"]),
+            "dig 0 does not have a positive length"
+        );
+        assert_eq!(
+            plan(&["R 2", "D 1", "L 2", "U -1"]),
+            "dig 3 does not have a positive length"
+        );
+        assert_eq!(plan(&["R 2", "L 2"]), "dig 1 doubles back along dig 0");
+        assert_eq!(
+            plan(&["D 1", "R 1", "U 1", "L 1", "D 0"]),
+            "dig 4 does not have a positive length"
+        );
+    }
 }
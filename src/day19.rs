@@ -1,28 +1,33 @@
-use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::collections::{BTreeMap, HashMap};
+use std::ops::{Range, RangeInclusive};
 
-use rayon::prelude::*;
+use num_bigint::BigUint;
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Debug)]
 struct Part {
-    x: u64,
-    m: u64,
-    a: u64,
-    s: u64,
+    ratings: Vec<Option<u64>>,
 }
 
 impl Part {
-    fn parse(s: &str) -> Self {
-        let mut parts = s[1..s.len() - 1].split(',');
-        Self {
-            x: parts.next().unwrap()[2..].parse().unwrap(),
-            m: parts.next().unwrap()[2..].parse().unwrap(),
-            a: parts.next().unwrap()[2..].parse().unwrap(),
-            s: parts.next().unwrap()[2..].parse().unwrap(),
+    fn parse<'a>(s: &'a str, categories: &mut HashMap<&'a str, usize>) -> Self {
+        let mut ratings = Vec::new();
+        for rating in s.trim_start_matches('{').trim_end_matches('}').split(',') {
+            let (name, value) = rating.split_once('=').unwrap();
+            let id = get_id(name, categories);
+            if ratings.len() <= id {
+                ratings.resize(id + 1, None);
+            }
+            ratings[id] = Some(value.parse().unwrap());
         }
+        Part { ratings }
     }
 
-    fn total_rating(self) -> u64 {
-        self.x + self.m + self.a + self.s
+    fn rating(&self, category: usize) -> Option<u64> {
+        self.ratings.get(category).copied().flatten()
+    }
+
+    fn total_rating(&self) -> u64 {
+        self.ratings.iter().flatten().sum()
     }
 }
 
@@ -43,7 +48,11 @@ struct Workflow {
 }
 
 impl Workflow {
-    fn parse<'a>(s: &'a str, map: &mut HashMap<&'a str, usize>) -> (usize, Self) {
+    fn parse<'a>(
+        s: &'a str,
+        map: &mut HashMap<&'a str, usize>,
+        categories: &HashMap<&str, usize>,
+    ) -> (usize, Self) {
         let (name, rhs) = s[..s.len() - 1].split_once('{').unwrap();
         let id = get_id(name, map);
         let mut rules = rhs.split(',');
@@ -51,101 +60,131 @@ impl Workflow {
         (
             id,
             Workflow {
-                rules: rules.map(|r| Rule::parse(r, map)).collect(),
+                rules: rules.map(|r| Rule::parse(r, map, categories)).collect(),
                 fallback,
             },
         )
     }
 
-    fn handle(&self, part: Part) -> Destination {
+    fn handle(&self, part: &Part) -> Destination {
         for rule in &self.rules {
-            if let Some(dest) = rule.handle(part) {
-                return dest;
+            if rule.condition.matches(part) {
+                return rule.destination;
             }
         }
         self.fallback
     }
+}
 
-    fn x_values(&self) -> impl Iterator<Item = u64> + '_ {
-        self.rules
-            .iter()
-            .filter(|r| r.field == Field::X)
-            .map(move |r| r.value + u64::from(!r.less_than))
-    }
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Comparison {
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+    Equal,
+}
 
-    fn m_values(&self) -> impl Iterator<Item = u64> + '_ {
-        self.rules
-            .iter()
-            .filter(|r| r.field == Field::M)
-            .map(move |r| r.value + u64::from(!r.less_than))
+impl Comparison {
+    fn parse(s: &str) -> Self {
+        match s {
+            "<" => Comparison::Less,
+            "<=" => Comparison::LessOrEqual,
+            ">" => Comparison::Greater,
+            ">=" => Comparison::GreaterOrEqual,
+            "==" => Comparison::Equal,
+            _ => panic!("unknown comparison {s}"),
+        }
     }
 
-    fn a_values(&self) -> impl Iterator<Item = u64> + '_ {
-        self.rules
-            .iter()
-            .filter(|r| r.field == Field::A)
-            .map(move |r| r.value + u64::from(!r.less_than))
+    fn symbol(self) -> &'static str {
+        match self {
+            Comparison::Less => "<",
+            Comparison::LessOrEqual => "<=",
+            Comparison::Greater => ">",
+            Comparison::GreaterOrEqual => ">=",
+            Comparison::Equal => "==",
+        }
     }
 
-    fn s_values(&self) -> impl Iterator<Item = u64> + '_ {
-        self.rules
-            .iter()
-            .filter(|r| r.field == Field::S)
-            .map(move |r| r.value + u64::from(!r.less_than))
+    fn matching(self, value: u64) -> Option<RangeInclusive<u64>> {
+        match self {
+            Comparison::Less => Some(0..=value.checked_sub(1)?),
+            Comparison::LessOrEqual => Some(0..=value),
+            Comparison::Greater => Some(value.checked_add(1)?..=u64::MAX),
+            Comparison::GreaterOrEqual => Some(value..=u64::MAX),
+            Comparison::Equal => Some(value..=value),
+        }
     }
 }
 
 #[derive(Clone, Copy, Debug)]
-struct Rule {
-    field: Field,
+struct Condition {
+    category: Option<usize>,
+    comparison: Comparison,
     value: u64,
-    less_than: bool,
-    destination: Destination,
 }
 
-impl Rule {
-    fn parse<'a>(s: &'a str, map: &mut HashMap<&'a str, usize>) -> Self {
-        let field = Field::parse(&s[..1]);
-        let less_than = &s[1..2] == "<";
-        let (value, destination) = s[2..].split_once(':').unwrap();
-        Rule {
-            field,
-            value: value.parse().unwrap(),
-            less_than,
-            destination: Destination::parse(destination, map),
+impl Condition {
+    fn parse(s: &str, categories: &HashMap<&str, usize>) -> Self {
+        let op_start = s.find(['<', '>', '=']).unwrap();
+        let op_len = if s[op_start + 1..].starts_with('=') {
+            2
+        } else {
+            1
+        };
+        Condition {
+            category: categories.get(&s[..op_start]).copied(),
+            comparison: Comparison::parse(&s[op_start..op_start + op_len]),
+            value: s[op_start + op_len..].parse().unwrap(),
         }
     }
 
-    fn handle(self, part: Part) -> Option<Destination> {
-        let part_value = match self.field {
-            Field::X => part.x,
-            Field::M => part.m,
-            Field::A => part.a,
-            Field::S => part.s,
+    fn matches(self, part: &Part) -> bool {
+        let Some((category, matching)) = self.category.zip(self.comparison.matching(self.value))
+        else {
+            return false;
         };
+        part.rating(category)
+            .is_some_and(|rating| matching.contains(&rating))
+    }
 
-        let lt_match = self.less_than && part_value < self.value;
-        let gt_match = !self.less_than && part_value > self.value;
-        (lt_match || gt_match).then_some(self.destination)
+    fn split(self, block: &Block) -> (Option<Block>, Vec<Block>) {
+        let Some((category, matching)) = self.category.zip(self.comparison.matching(self.value))
+        else {
+            return (None, vec![block.clone()]);
+        };
+        let range = &block.ranges[category];
+        let (low, high) = (*matching.start(), matching.end().saturating_add(1));
+        let below = range.start..range.end.min(low);
+        let above = range.start.max(high)..range.end;
+        let matching = range.start.max(low)..range.end.min(high);
+        (
+            block.with(category, matching),
+            [below, above]
+                .into_iter()
+                .filter_map(|range| block.with(category, range))
+                .collect(),
+        )
     }
 }
 
-#[derive(Clone, Copy, Debug, PartialEq, Eq)]
-enum Field {
-    X,
-    M,
-    A,
-    S,
+#[derive(Clone, Copy, Debug)]
+struct Rule {
+    condition: Condition,
+    destination: Destination,
 }
 
-impl Field {
-    fn parse(s: &str) -> Self {
-        match s {
-            "x" => Field::X,
-            "m" => Field::M,
-            "a" => Field::A,
-            "s" => Field::S,
-            _ => panic!(),
+impl Rule {
+    fn parse<'a>(
+        s: &'a str,
+        map: &mut HashMap<&'a str, usize>,
+        categories: &HashMap<&str, usize>,
+    ) -> Self {
+        let (condition, destination) = s.split_once(':').unwrap();
+        Rule {
+            condition: Condition::parse(condition, categories),
+            destination: Destination::parse(destination, map),
         }
     }
 }
@@ -167,76 +206,700 @@ impl Destination {
     }
 }
 
-fn accept_part(part: Part, workflows: &[Workflow]) -> bool {
-    let mut dest = Destination::Other(0);
-    while let Destination::Other(id) = dest {
-        dest = workflows[id].handle(part);
+#[derive(Clone, Debug)]
+struct Block {
+    ranges: Vec<Range<u64>>,
+}
+
+impl Block {
+    fn new(categories: usize, range: RangeInclusive<u64>) -> Self {
+        Block {
+            ranges: vec![*range.start()..*range.end() + 1; categories],
+        }
+    }
+
+    fn with(&self, category: usize, range: Range<u64>) -> Option<Self> {
+        if range.is_empty() {
+            return None;
+        }
+        let mut block = self.clone();
+        block.ranges[category] = range;
+        Some(block)
+    }
+
+    fn volume(&self) -> BigUint {
+        self.ranges
+            .iter()
+            .map(|range| BigUint::from(range.end - range.start))
+            .product()
+    }
+}
+
+fn count_accepted(destination: Destination, block: Block, workflows: &Workflows) -> BigUint {
+    let id = match destination {
+        Destination::Accepted => return block.volume(),
+        Destination::Rejected => return BigUint::default(),
+        Destination::Other(id) => id,
+    };
+
+    let workflow = workflows.get(id);
+    count_rules(&workflow.rules, workflow.fallback, block, workflows)
+}
+
+fn count_rules(
+    rules: &[Rule],
+    fallback: Destination,
+    block: Block,
+    workflows: &Workflows,
+) -> BigUint {
+    let Some((rule, rules)) = rules.split_first() else {
+        return count_accepted(fallback, block, workflows);
+    };
+    let (matching, rest) = rule.condition.split(&block);
+    let matching = matching.map_or(BigUint::default(), |block| {
+        count_accepted(rule.destination, block, workflows)
+    });
+    let rest: BigUint = rest
+        .into_iter()
+        .map(|block| count_rules(rules, fallback, block, workflows))
+        .sum();
+    matching + rest
+}
+
+fn split_region(condition: Condition, region: Vec<Block>) -> (Vec<Block>, Vec<Block>) {
+    let (mut matching, mut rest) = (Vec::new(), Vec::new());
+    for block in region {
+        let (block_matching, block_rest) = condition.split(&block);
+        matching.extend(block_matching);
+        rest.extend(block_rest);
+    }
+    (matching, rest)
+}
+
+const RATINGS: RangeInclusive<u64> = 1..=4000;
+
+struct Workflows<'a> {
+    names: Vec<&'a str>,
+    categories: usize,
+    workflows: Vec<Option<Workflow>>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum Issue {
+    Undefined(String),
+    Unreachable(String),
+    Cycle(Vec<String>),
+    DeadRule(String, usize),
+}
+
+impl std::fmt::Display for Issue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Issue::Undefined(name) => write!(f, "workflow {name} is used but never defined"),
+            Issue::Unreachable(name) => write!(f, "workflow {name} is unreachable from in"),
+            Issue::Cycle(names) => write!(f, "workflows form a cycle: {}", names.join(" -> ")),
+            Issue::DeadRule(name, rule) => {
+                write!(f, "rule {rule} of workflow {name} never matches")
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+enum Decision {
+    Accept,
+    Reject,
+    Test {
+        condition: Condition,
+        then: Box<Decision>,
+        otherwise: Box<Decision>,
+    },
+}
+
+impl Decision {
+    #[cfg(test)]
+    fn evaluate(&self, part: &Part) -> bool {
+        match self {
+            Decision::Accept => true,
+            Decision::Reject => false,
+            Decision::Test {
+                condition,
+                then,
+                otherwise,
+            } => {
+                if condition.matches(part) {
+                    then.evaluate(part)
+                } else {
+                    otherwise.evaluate(part)
+                }
+            }
+        }
+    }
+
+    fn render(&self, categories: &[&str], depth: usize, out: &mut String) {
+        let indent = "  ".repeat(depth);
+        match self {
+            Decision::Accept => *out += &format!("{indent}A\n"),
+            Decision::Reject => *out += &format!("{indent}R\n"),
+            Decision::Test {
+                condition,
+                then,
+                otherwise,
+            } => {
+                let category = condition
+                    .category
+                    .map_or("?", |category| categories[category]);
+                *out += &format!(
+                    "{indent}if {category}{}{}\n",
+                    condition.comparison.symbol(),
+                    condition.value
+                );
+                then.render(categories, depth + 1, out);
+                *out += &format!("{indent}else\n");
+                otherwise.render(categories, depth + 1, out);
+            }
+        }
+    }
+}
+
+impl<'a> Workflows<'a> {
+    fn parse(s: &'a str, categories: &HashMap<&str, usize>) -> Self {
+        let mut map = HashMap::from([("in", 0)]);
+        let mut workflows = BTreeMap::new();
+        for line in s.lines() {
+            let (id, workflow) = Workflow::parse(line, &mut map, categories);
+            workflows.insert(id, workflow);
+        }
+
+        let mut names = vec![""; map.len()];
+        for (name, id) in map {
+            names[id] = name;
+        }
+        let workflows = (0..names.len()).map(|id| workflows.remove(&id)).collect();
+        Workflows {
+            names,
+            categories: categories.len(),
+            workflows,
+        }
+    }
+
+    fn get(&self, id: usize) -> &Workflow {
+        self.workflows[id]
+            .as_ref()
+            .unwrap_or_else(|| panic!("workflow {} is not defined", self.names[id]))
+    }
+
+    fn name(&self, destination: Destination) -> &str {
+        match destination {
+            Destination::Accepted => "A",
+            Destination::Rejected => "R",
+            Destination::Other(id) => self.names[id],
+        }
+    }
+
+    fn successors(&self, id: usize) -> impl Iterator<Item = usize> + '_ {
+        self.workflows[id]
+            .iter()
+            .flat_map(|workflow| {
+                workflow
+                    .rules
+                    .iter()
+                    .map(|rule| rule.destination)
+                    .chain(Some(workflow.fallback))
+            })
+            .filter_map(|destination| match destination {
+                Destination::Other(id) => Some(id),
+                _ => None,
+            })
+    }
+
+    fn find_cycle(
+        &self,
+        id: usize,
+        path: &mut Vec<usize>,
+        done: &mut [bool],
+    ) -> Option<Vec<usize>> {
+        if let Some(start) = path.iter().position(|&other| other == id) {
+            return Some(path[start..].iter().copied().chain(Some(id)).collect());
+        }
+        if done[id] {
+            return None;
+        }
+        path.push(id);
+        let successors: Vec<_> = self.successors(id).collect();
+        for next in successors {
+            if let Some(cycle) = self.find_cycle(next, path, done) {
+                return Some(cycle);
+            }
+        }
+        path.pop();
+        done[id] = true;
+        None
+    }
+
+    fn check(&self) -> Result<(), Issue> {
+        let mut done = vec![false; self.names.len()];
+        if let Some(cycle) = self.find_cycle(0, &mut Vec::new(), &mut done) {
+            let names = cycle.into_iter().map(|id| self.names[id].to_string());
+            return Err(Issue::Cycle(names.collect()));
+        }
+        for (id, reachable) in done.into_iter().enumerate() {
+            if reachable && self.workflows[id].is_none() {
+                return Err(Issue::Undefined(self.names[id].to_string()));
+            }
+        }
+        Ok(())
+    }
+
+    fn validate(&self) -> Vec<Issue> {
+        let mut issues = Vec::new();
+
+        for (id, workflow) in self.workflows.iter().enumerate() {
+            if workflow.is_none() {
+                issues.push(Issue::Undefined(self.names[id].to_string()));
+            }
+        }
+
+        let mut reachable = vec![false; self.names.len()];
+        let mut stack = vec![0];
+        while let Some(id) = stack.pop() {
+            if !std::mem::replace(&mut reachable[id], true) {
+                stack.extend(self.successors(id));
+            }
+        }
+        for (id, &reachable) in reachable.iter().enumerate() {
+            if !reachable {
+                issues.push(Issue::Unreachable(self.names[id].to_string()));
+            }
+        }
+
+        let mut done = vec![false; self.names.len()];
+        for id in 0..self.names.len() {
+            if let Some(cycle) = self.find_cycle(id, &mut Vec::new(), &mut done) {
+                for &id in &cycle {
+                    done[id] = true;
+                }
+                let names = cycle.into_iter().map(|id| self.names[id].to_string());
+                issues.push(Issue::Cycle(names.collect()));
+            }
+        }
+
+        let mut live: Vec<_> = self
+            .workflows
+            .iter()
+            .map(|workflow| vec![false; workflow.as_ref().map_or(0, |w| w.rules.len())])
+            .collect();
+        let region = vec![Block::new(self.categories, RATINGS)];
+        self.mark_live_rules(Destination::Other(0), region, &mut Vec::new(), &mut live);
+        for (id, live) in live.into_iter().enumerate() {
+            if !reachable[id] {
+                continue;
+            }
+            for (index, _) in live.into_iter().enumerate().filter(|(_, live)| !live) {
+                issues.push(Issue::DeadRule(self.names[id].to_string(), index));
+            }
+        }
+
+        issues
+    }
+
+    fn mark_live_rules(
+        &self,
+        destination: Destination,
+        mut region: Vec<Block>,
+        path: &mut Vec<usize>,
+        live: &mut [Vec<bool>],
+    ) {
+        let Destination::Other(id) = destination else {
+            return;
+        };
+        let Some(workflow) = &self.workflows[id] else {
+            return;
+        };
+        if path.contains(&id) {
+            return;
+        }
+        path.push(id);
+        for (index, rule) in workflow.rules.iter().enumerate() {
+            let (matching, rest) = split_region(rule.condition, region);
+            if !matching.is_empty() {
+                live[id][index] = true;
+                self.mark_live_rules(rule.destination, matching, path, live);
+            }
+            region = rest;
+        }
+        if !region.is_empty() {
+            self.mark_live_rules(workflow.fallback, region, path, live);
+        }
+        path.pop();
+    }
+
+    fn compile(&self) -> Result<Decision, Issue> {
+        self.check()?;
+        let region = vec![Block::new(self.categories, RATINGS)];
+        self.compile_destination(Destination::Other(0), region)
+    }
+
+    fn compile_destination(
+        &self,
+        destination: Destination,
+        region: Vec<Block>,
+    ) -> Result<Decision, Issue> {
+        let id = match destination {
+            Destination::Accepted => return Ok(Decision::Accept),
+            Destination::Rejected => return Ok(Decision::Reject),
+            Destination::Other(id) => id,
+        };
+        let workflow = self.workflows[id]
+            .as_ref()
+            .ok_or_else(|| Issue::Undefined(self.names[id].to_string()))?;
+        self.compile_rules(&workflow.rules, workflow.fallback, region)
+    }
+
+    fn compile_rules(
+        &self,
+        rules: &[Rule],
+        fallback: Destination,
+        region: Vec<Block>,
+    ) -> Result<Decision, Issue> {
+        let Some((rule, rules)) = rules.split_first() else {
+            return self.compile_destination(fallback, region);
+        };
+        match split_region(rule.condition, region) {
+            (matching, rest) if matching.is_empty() && rest.is_empty() => Ok(Decision::Reject),
+            (matching, rest) if rest.is_empty() => {
+                self.compile_destination(rule.destination, matching)
+            }
+            (matching, rest) if matching.is_empty() => self.compile_rules(rules, fallback, rest),
+            (matching, rest) => Ok(Decision::Test {
+                condition: rule.condition,
+                then: Box::new(self.compile_destination(rule.destination, matching)?),
+                otherwise: Box::new(self.compile_rules(rules, fallback, rest)?),
+            }),
+        }
+    }
+
+    fn explain(&self, part: &Part) -> String {
+        let mut route = vec![self.names[0]];
+        let mut seen = vec![false; self.names.len()];
+        let mut destination = Destination::Other(0);
+        while let Destination::Other(id) = destination {
+            if std::mem::replace(&mut seen[id], true) {
+                route.push("... (cycle)");
+                break;
+            }
+            let Some(workflow) = &self.workflows[id] else {
+                route.push("? (undefined)");
+                break;
+            };
+            destination = workflow.handle(part);
+            route.push(self.name(destination));
+        }
+        route.join(" -> ")
+    }
+
+    fn accept_part(&self, part: &Part) -> bool {
+        let mut dest = Destination::Other(0);
+        while let Destination::Other(id) = dest {
+            dest = self.get(id).handle(part);
+        }
+        matches!(dest, Destination::Accepted)
+    }
+}
+
+pub fn debug(input: &str, mode: &str) -> String {
+    let (workflows, parts) = input.split_once("\n\n").unwrap();
+    let mut categories = HashMap::new();
+    let parts: Vec<_> = parts
+        .lines()
+        .map(|line| Part::parse(line, &mut categories))
+        .collect();
+    let workflows = Workflows::parse(workflows, &categories);
+
+    let mut out = String::new();
+    match mode {
+        "" | "validate" => {
+            for issue in workflows.validate() {
+                out += &format!("{issue}\n");
+            }
+        }
+        "explain" => {
+            for part in &parts {
+                out += &format!("{}\n", workflows.explain(part));
+            }
+        }
+        "compile" => match workflows.compile() {
+            Ok(decision) => {
+                let mut names = vec![""; categories.len()];
+                for (name, id) in categories {
+                    names[id] = name;
+                }
+                decision.render(&names, 0, &mut out);
+            }
+            Err(issue) => out += &format!("{issue}\n"),
+        },
+        _ => panic!("unknown day19 debug mode {mode:?}"),
     }
-    matches!(dest, Destination::Accepted)
+    out
 }
 
 pub fn part1(input: &str) -> u64 {
     let (workflows, parts) = input.split_once("\n\n").unwrap();
-    let mut map = HashMap::from([("in", 0)]);
-    let workflows: BTreeMap<_, _> = workflows
+    let mut categories = HashMap::new();
+    let parts: Vec<_> = parts
         .lines()
-        .map(|l| Workflow::parse(l, &mut map))
+        .map(|line| Part::parse(line, &mut categories))
         .collect();
-    let workflows: Vec<_> = workflows.into_values().collect();
+    let workflows = Workflows::parse(workflows, &categories);
+    if let Err(issue) = workflows.check() {
+        panic!("{issue}");
+    }
 
     parts
-        .lines()
-        .map(Part::parse)
-        .filter(|part| accept_part(*part, &workflows))
+        .iter()
+        .filter(|part| workflows.accept_part(part))
         .map(Part::total_rating)
         .sum()
 }
 
-pub fn part2(input: &str) -> u64 {
-    let (workflows, _) = input.split_once("\n\n").unwrap();
-    let mut map = HashMap::from([("in", 0)]);
-    let workflows: BTreeMap<_, _> = workflows
-        .lines()
-        .map(|l| Workflow::parse(l, &mut map))
-        .collect();
-    let workflows: Vec<_> = workflows.into_values().collect();
-
-    let x_values: BTreeSet<_> = workflows.iter().flat_map(Workflow::x_values).collect();
-    let m_values: BTreeSet<_> = workflows.iter().flat_map(Workflow::m_values).collect();
-    let a_values: BTreeSet<_> = workflows.iter().flat_map(Workflow::a_values).collect();
-    let s_values: BTreeSet<_> = workflows.iter().flat_map(Workflow::s_values).collect();
-    let x_values: Vec<_> = x_values.into_iter().chain(Some(4001)).collect();
-    let m_values: Vec<_> = m_values.into_iter().chain(Some(4001)).collect();
-    let a_values: Vec<_> = a_values.into_iter().chain(Some(4001)).collect();
-    let s_values: Vec<_> = s_values.into_iter().chain(Some(4001)).collect();
-
-    let (mut x_windows, mut x) = (Vec::new(), 1);
-    for &next_x in &x_values {
-        x_windows.push((x, next_x));
-        x = next_x;
-    }
-
-    x_windows
-        .into_par_iter()
-        .map(|(x, next_x)| {
-            let mut count = 0;
-            let mut m = 1;
-            for &next_m in &m_values {
-                let mut a = 1;
-                for &next_a in &a_values {
-                    let mut s = 1;
-                    for &next_s in &s_values {
-                        let part = Part { x, m, a, s };
-                        if accept_part(part, &workflows) {
-                            count += (next_x - x) * (next_m - m) * (next_a - a) * (next_s - s);
-                        }
-                        s = next_s;
-                    }
-                    a = next_a;
-                }
-                m = next_m;
+pub fn part2(input: &str) -> BigUint {
+    let (workflows, parts) = input.split_once("\n\n").unwrap();
+    let mut categories = HashMap::new();
+    for line in parts.lines() {
+        Part::parse(line, &mut categories);
+    }
+    let workflows = Workflows::parse(workflows, &categories);
+    if let Err(issue) = workflows.check() {
+        panic!("{issue}");
+    }
+    let block = Block::new(workflows.categories, RATINGS);
+    count_accepted(Destination::Other(0), block, &workflows)
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    use super::*;
+
+    const EXAMPLE: &str = "px{a<2006:qkq,m>2090:A,rfg}\npv{a>1716:R,A}\nlnx{m>1548:A,A}\nrfg{s<537:gd,x>2440:R,A}\nqs{s>3448:A,lnx}\nqkq{x<1416:A,crn}\ncrn{x>2662:A,R}\nin{s<1351:px,qqz}\nqqz{s>2770:qs,m<1801:hdj,R}\ngd{a>3333:R,R}\nhdj{m>838:A,pv}\n\n{x=787,m=2655,a=1222,s=2876}\n{x=1679,m=44,a=2067,s=496}\n{x=2036,m=264,a=79,s=2244}\n{x=2461,m=1339,a=466,s=291}\n{x=2127,m=1623,a=2188,s=1013}";
+
+    fn parse_example(input: &str) -> (Workflows<'_>, Vec<Part>) {
+        let (workflows, parts) = input.split_once("\n\n").unwrap();
+        let mut categories = HashMap::new();
+        let parts = parts
+            .lines()
+            .map(|line| Part::parse(line, &mut categories))
+            .collect();
+        (Workflows::parse(workflows, &categories), parts)
+    }
+
+    #[test]
+    fn example() {
+        assert_eq!(part1(EXAMPLE), 19114);
+        assert_eq!(part2(EXAMPLE), BigUint::from(167409079868000u64));
+    }
+
+    #[test]
+    fn validate_reports_issues() {
+        let (workflows, _) = parse_example(EXAMPLE);
+        assert_eq!(workflows.validate(), []);
+
+        let input = "in{x>5:a,b}\na{x<3:A,x<2:R,in}\nc{x>1:A,R}\n\n{x=1}";
+        let (workflows, _) = parse_example(input);
+        assert_eq!(
+            workflows.validate(),
+            [
+                Issue::Undefined("b".to_string()),
+                Issue::Unreachable("c".to_string()),
+                Issue::Cycle(vec!["in".to_string(), "a".to_string(), "in".to_string()]),
+                Issue::DeadRule("a".to_string(), 0),
+                Issue::DeadRule("a".to_string(), 1),
+            ]
+        );
+
+        assert_eq!(
+            workflows.check().unwrap_err().to_string(),
+            "workflows form a cycle: in -> a -> in"
+        );
+
+        let (workflows, _) = parse_example("in{x<10:a,R}\na{x>20:A,R}\n\n{x=1}");
+        assert_eq!(workflows.validate(), [Issue::DeadRule("a".to_string(), 0)]);
+        let (workflows, _) = parse_example("in{x<10:a,b}\na{x>20:A,R}\nb{x>20:A,R}\n\n{x=1}");
+        assert_eq!(workflows.validate(), [Issue::DeadRule("a".to_string(), 0)]);
+    }
+
+    #[test]
+    #[should_panic(expected = "workflows form a cycle: in -> a -> in")]
+    fn part1_rejects_cycles() {
+        part1("in{x>5:a,R}\na{x<3:A,in}\n\n{x=1}\n{x=9}");
+    }
+
+    #[test]
+    #[should_panic(expected = "workflows form a cycle: in -> a -> in")]
+    fn part2_rejects_cycles() {
+        part2("in{x>5:a,R}\na{x<3:A,in}\n\n{x=1}");
+    }
+
+    #[test]
+    #[should_panic(expected = "workflow b is used but never defined")]
+    fn part2_rejects_undefined_workflows() {
+        part2("in{x>5:b,R}\n\n{x=1}");
+    }
+
+    #[test]
+    fn compile_matches_workflows() {
+        let (workflows, parts) = parse_example(EXAMPLE);
+        let decision = workflows.compile().unwrap();
+        for part in &parts {
+            assert_eq!(decision.evaluate(part), workflows.accept_part(part));
+        }
+
+        let (workflows, _) = parse_example("in{x<10:a,R}\na{x>20:R,A}\n\n{x=1}");
+        assert!(matches!(workflows.compile(), Ok(Decision::Test { .. })));
+        let (workflows, _) = parse_example("in{x>0:A,R}\n\n{x=1}");
+        assert!(matches!(workflows.compile(), Ok(Decision::Accept)));
+        let (workflows, _) = parse_example("in{x>5:b,R}\n\n{x=1}");
+        assert_eq!(
+            workflows.compile().unwrap_err(),
+            Issue::Undefined("b".to_string())
+        );
+    }
+
+    #[test]
+    fn explain_routes() {
+        let (workflows, parts) = parse_example(EXAMPLE);
+        let routes: Vec<_> = parts.iter().map(|part| workflows.explain(part)).collect();
+        assert_eq!(
+            routes,
+            [
+                "in -> qqz -> qs -> lnx -> A",
+                "in -> px -> rfg -> gd -> R",
+                "in -> qqz -> hdj -> pv -> A",
+                "in -> px -> qkq -> crn -> R",
+                "in -> px -> rfg -> A",
+            ]
+        );
+
+        let (workflows, parts) = parse_example("in{x>5:a,b}\na{x<9:A,in}\n\n{x=1}\n{x=9}");
+        assert_eq!(workflows.explain(&parts[0]), "in -> b -> ? (undefined)");
+        assert_eq!(workflows.explain(&parts[1]), "in -> a -> in -> ... (cycle)");
+    }
+
+    #[test]
+    fn debug_modes() {
+        let input = "in{x<10:a,R}\na{m>5:R,x>20:A,A}\nb{x>1:A,R}\n\n{x=1,m=9}\n{x=2,m=1}";
+        assert_eq!(
+            debug(input, ""),
+            "workflow b is unreachable from in\nrule 1 of workflow a never matches\n"
+        );
+        assert_eq!(debug(input, "explain"), "in -> a -> R\nin -> a -> A\n");
+        assert_eq!(
+            debug(input, "compile"),
+            "if x<10\n  if m>5\n    R\n  else\n    A\nelse\n  R\n"
+        );
+        assert_eq!(
+            debug("in{x>5:b,R}\n\n{x=1}", "compile"),
+            "workflow b is used but never defined\n"
+        );
+    }
+
+    #[test]
+    fn undeclared_categories_never_match() {
+        let input = "in{q>5:A,R}\n\n{x=1}";
+        assert_eq!(part1(input), 0);
+        assert_eq!(part2(input), BigUint::from(0u64));
+        let (workflows, _) = parse_example(input);
+        assert_eq!(workflows.validate(), [Issue::DeadRule("in".to_string(), 0)]);
+    }
+
+    #[test]
+    fn many_categories() {
+        let input = "in{a>0:A,A}\n\n{a=1,b=1,c=1,d=1,e=1,f=1}";
+        assert_eq!(part2(input), BigUint::from(4000u64).pow(6));
+        let input = "in{a>3999:A,R}\n\n{a=1,b=1,c=1,d=1,e=1,f=1,g=1,h=1,i=1,j=1,k=1,l=1}";
+        assert_eq!(part2(input), BigUint::from(4000u64).pow(11));
+    }
+
+    #[test]
+    fn custom_categories_and_comparisons() {
+        let input = "in{size==3:A,weight<=10:big,R}\nbig{weight>=5:R,A}\n\n{size=3,weight=50}\n{size=2,weight=4}\n{size=1,weight=7}\n{weight=2}";
+        let (workflows, parts) = parse_example(input);
+        let accepted: Vec<_> = parts
+            .iter()
+            .map(|part| workflows.accept_part(part))
+            .collect();
+        assert_eq!(accepted, [true, true, false, true]);
+        assert_eq!(part1(input), 53 + 6 + 2);
+        assert_eq!(part2(input), BigUint::from(4000u64 + 3999 * 4));
+    }
+
+    #[test]
+    fn comparisons_at_the_edges() {
+        let input = "in{x<0:R,x>18446744073709551615:R,x==18446744073709551615:a,R}\na{x<=18446744073709551615:b,R}\nb{x>=18446744073709551615:A,R}\n\n{x=18446744073709551615}\n{x=0}";
+        let (workflows, parts) = parse_example(input);
+        assert!(workflows.accept_part(&parts[0]));
+        assert!(!workflows.accept_part(&parts[1]));
+        assert_eq!(part2(input), BigUint::from(0u64));
+        assert_eq!(
+            workflows.validate(),
+            [
+                Issue::DeadRule("in".to_string(), 0),
+                Issue::DeadRule("in".to_string(), 1),
+                Issue::DeadRule("in".to_string(), 2),
+                Issue::DeadRule("a".to_string(), 0),
+                Issue::DeadRule("b".to_string(), 0),
+            ]
+        );
+    }
+
+    fn random_destination(rng: &mut StdRng, names: &[&str], from: usize) -> String {
+        match rng.gen_range(from + 1..names.len() + 2) {
+            j if j == names.len() => "A".to_string(),
+            j if j > names.len() => "R".to_string(),
+            j => names[j].to_string(),
+        }
+    }
+
+    fn random_rule(rng: &mut StdRng, names: &[&str], from: usize) -> String {
+        let category = ["x", "m"][rng.gen_range(0..2)];
+        let comparison = ["<", ">", "<=", ">=", "=="][rng.gen_range(0..5)];
+        let value = rng.gen_range(0..14);
+        let destination = random_destination(rng, names, from);
+        format!("{category}{comparison}{value}:{destination}")
+    }
+
+    #[test]
+    fn count_accepted_matches_brute_force() {
+        let mut rng = StdRng::seed_from_u64(19);
+        let names = ["in", "w1", "w2", "w3"];
+        for _ in 0..100 {
+            let mut lines = Vec::new();
+            for (i, name) in names.iter().enumerate() {
+                let mut rules: Vec<_> = (0..rng.gen_range(0..4))
+                    .map(|_| random_rule(&mut rng, &names, i))
+                    .collect();
+                rules.push(random_destination(&mut rng, &names, i));
+                lines.push(format!("{name}{{{}}}", rules.join(",")));
             }
-            count
-        })
-        .sum()
+            let input = lines.join("\n") + "\n\n{x=0,m=0}";
+            let (workflows, _) = parse_example(&input);
+
+            let brute_force = (1..=12)
+                .flat_map(|x| (1..=12).map(move |m| vec![Some(x), Some(m)]))
+                .filter(|ratings| {
+                    workflows.accept_part(&Part {
+                        ratings: ratings.clone(),
+                    })
+                })
+                .count();
+            let block = Block::new(workflows.categories, 1..=12);
+            assert_eq!(
+                count_accepted(Destination::Other(0), block, &workflows),
+                BigUint::from(brute_force),
+                "{input}"
+            );
+        }
+    }
 }
@@ -1,28 +1,20 @@
 use std::collections::{HashMap, VecDeque};
-use std::iter::repeat_with;
 
 use crate::shared::lcm;
 
 #[derive(Clone, Debug)]
-struct Setup<'a> {
+struct Network<'a> {
     broadcaster: Vec<&'a str>,
     flip_flops: HashMap<&'a str, FlipFlop<'a>>,
     conjunctions: HashMap<&'a str, Conjunction<'a>>,
-    special: HashMap<&'a str, Option<u64>>,
     presses: u64,
 }
 
-impl<'a> Setup<'a> {
+impl<'a> Network<'a> {
     fn parse(input: &'a str) -> Self {
         let mut broadcaster = Vec::new();
         let mut flip_flops = HashMap::new();
-        let mut conjunctions = HashMap::from([(
-            "rx",
-            Conjunction {
-                recent: HashMap::new(),
-                modules: Vec::new(),
-            },
-        )]);
+        let mut conjunctions = HashMap::new();
         for line in input.lines() {
             let (lhs, rhs) = line.split_once(" -> ").unwrap();
             if line.starts_with("%") {
@@ -46,71 +38,89 @@ impl<'a> Setup<'a> {
             }
         }
 
-        for (name, flip_flop) in &flip_flops {
-            for module in &flip_flop.modules {
-                if let Some(conj) = conjunctions.get_mut(module) {
-                    conj.recent.insert(name, Pulse::Low);
-                }
-            }
-        }
-        for (name, conjunction) in conjunctions.clone() {
-            for module in &conjunction.modules {
-                if let Some(conj) = conjunctions.get_mut(module) {
-                    conj.recent.insert(name, Pulse::Low);
-                }
-            }
-        }
-
-        let last = conjunctions["rx"].recent.keys().next().unwrap();
-        let special = conjunctions[last]
-            .recent
-            .keys()
-            .flat_map(|c| conjunctions[c].recent.keys().map(|s| (*s, None)))
-            .collect();
-
-        Setup {
+        let mut network = Network {
             broadcaster,
             flip_flops,
             conjunctions,
-            special,
             presses: 0,
+        };
+        let names: Vec<_> = network.conjunctions.keys().copied().collect();
+        for name in names {
+            let inputs = network.inputs(name);
+            let conjunction = network.conjunctions.get_mut(name).unwrap();
+            conjunction.recent = inputs.into_iter().map(|i| (i, Pulse::Low)).collect();
         }
+        network
     }
 
-    fn button(&mut self) -> (u64, u64, bool) {
+    fn outputs(&self) -> impl Iterator<Item = (&'a str, &[&'a str])> + '_ {
+        let flip_flops = self
+            .flip_flops
+            .iter()
+            .map(|(name, flip_flop)| (*name, &flip_flop.modules[..]));
+        let conjunctions = self
+            .conjunctions
+            .iter()
+            .map(|(name, conjunction)| (*name, &conjunction.modules[..]));
+        std::iter::once(("broadcaster", &self.broadcaster[..]))
+            .chain(flip_flops)
+            .chain(conjunctions)
+    }
+
+    fn inputs(&self, module: &str) -> Vec<&'a str> {
+        self.outputs()
+            .filter(|(_, modules)| modules.contains(&module))
+            .map(|(name, _)| name)
+            .collect()
+    }
+
+    fn press(&mut self, mut observe: impl FnMut(Signal<'a>)) {
         self.presses += 1;
-        let (mut low_pulses, mut high_pulses) = (1, 0);
-        let mut queue: VecDeque<_> = self.broadcaster.iter().map(Signal::broadcaster).collect();
+        let mut queue = VecDeque::from([Signal {
+            from: "button",
+            to: "broadcaster",
+            pulse: Pulse::Low,
+        }]);
         while let Some(signal) = queue.pop_front() {
-            if signal.pulse.is_high() {
-                high_pulses += 1;
-            } else {
-                low_pulses += 1;
-            }
-            if let Some(flip_flop) = self.flip_flops.get_mut(signal.to) {
+            observe(signal);
+            if signal.to == "broadcaster" {
+                for module in &self.broadcaster {
+                    queue.push_back(Signal {
+                        from: signal.to,
+                        to: module,
+                        pulse: signal.pulse,
+                    });
+                }
+            } else if let Some(flip_flop) = self.flip_flops.get_mut(signal.to) {
                 flip_flop.handle(signal, &mut queue);
             } else if let Some(conjunction) = self.conjunctions.get_mut(signal.to) {
                 conjunction.handle(signal, &mut queue);
             }
+        }
+    }
 
-            for (special, presses) in self.special.iter_mut() {
-                if presses.is_none()
-                    && self.conjunctions[special]
-                        .recent
-                        .values()
-                        .copied()
-                        .all(Pulse::is_high)
-                {
-                    *presses = Some(self.presses);
-                }
-            }
+    fn count_pulses(&mut self, presses: u64) -> PulseCounts {
+        let mut counts = PulseCounts::default();
+        for _ in 0..presses {
+            self.press(|signal| counts.record(signal));
         }
+        counts
+    }
+}
 
-        (
-            low_pulses,
-            high_pulses,
-            self.special.values().all(Option::is_some),
-        )
+#[derive(Clone, Copy, Debug, Default)]
+struct PulseCounts {
+    low: u64,
+    high: u64,
+}
+
+impl PulseCounts {
+    fn record(&mut self, signal: Signal) {
+        if signal.pulse.is_high() {
+            self.high += 1;
+        } else {
+            self.low += 1;
+        }
     }
 }
 
@@ -121,16 +131,6 @@ struct Signal<'a> {
     pulse: Pulse,
 }
 
-impl<'a> Signal<'a> {
-    fn broadcaster(to: &&'a str) -> Self {
-        Signal {
-            from: "broadcaster",
-            to: *to,
-            pulse: Pulse::Low,
-        }
-    }
-}
-
 #[derive(Clone, Copy, Debug)]
 enum Pulse {
     Low,
@@ -190,23 +190,163 @@ impl<'a> Conjunction<'a> {
     }
 }
 
+#[derive(Debug)]
+enum StructureError {
+    Inputs(String, usize),
+    NotConjunction(String),
+    NoCycle(String, u64),
+    NotPeriodic(String, u64, u64),
+}
+
+impl std::fmt::Display for StructureError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StructureError::Inputs(name, count) => {
+                write!(f, "{name} has {count} inputs, expected exactly one")
+            }
+            StructureError::NotConjunction(name) => write!(f, "{name} is not a conjunction"),
+            StructureError::NoCycle(name, presses) => {
+                write!(f, "{name} did not cycle within {presses} presses")
+            }
+            StructureError::NotPeriodic(name, first, second) => write!(
+                f,
+                "{name} sent high pulses after {first} and {second} presses, not a clean cycle"
+            ),
+        }
+    }
+}
+
+const MAX_PRESSES: u64 = 1 << 16;
+
+fn presses_until_low(network: &mut Network, target: &str) -> Result<u64, StructureError> {
+    let inputs = network.inputs(target);
+    let [last] = inputs[..] else {
+        return Err(StructureError::Inputs(target.to_string(), inputs.len()));
+    };
+    let counters = network.inputs(last);
+    if counters.is_empty() {
+        return Err(StructureError::Inputs(last.to_string(), 0));
+    }
+    for name in Some(&last).into_iter().chain(&counters) {
+        if !network.conjunctions.contains_key(name) {
+            return Err(StructureError::NotConjunction(name.to_string()));
+        }
+    }
+
+    let mut highs: HashMap<_, _> = counters.iter().map(|name| (*name, Vec::new())).collect();
+    while network.presses < MAX_PRESSES && highs.values().any(|presses| presses.len() < 2) {
+        let press = network.presses + 1;
+        network.press(|signal| {
+            if signal.to == last && signal.pulse.is_high() {
+                let presses = highs.get_mut(signal.from).unwrap();
+                if presses.last() != Some(&press) {
+                    presses.push(press);
+                }
+            }
+        });
+    }
+
+    let mut total = 1;
+    for (name, presses) in highs {
+        match presses[..] {
+            [first, second, ..] if second == 2 * first => total = lcm(total, first),
+            [first, second, ..] => {
+                return Err(StructureError::NotPeriodic(name.to_string(), first, second));
+            }
+            _ => return Err(StructureError::NoCycle(name.to_string(), MAX_PRESSES)),
+        }
+    }
+    Ok(total)
+}
+
 pub fn part1(input: &str) -> u64 {
-    let mut setup = Setup::parse(input);
-    let (low_pulses, high_pulses) = repeat_with(|| setup.button())
-        .take(1000)
-        .fold((0, 0), |x, y| (x.0 + y.0, x.1 + y.1));
-    low_pulses * high_pulses
+    let counts = Network::parse(input).count_pulses(1000);
+    counts.low * counts.high
 }
 
 pub fn part2(input: &str) -> u64 {
-    let mut setup = Setup::parse(input);
-    let mut done = false;
-    while !done {
-        (_, _, done) = setup.button();
+    let mut network = Network::parse(input);
+    presses_until_low(&mut network, "rx").unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn examples() {
+        let input = "broadcaster -> a, b, c\n%a -> b\n%b -> c\n%c -> inv\n&inv -> a";
+        assert_eq!(part1(input), 32000000);
+        let input = "broadcaster -> a\n%a -> inv, con\n&inv -> b\n%b -> con\n&con -> output";
+        assert_eq!(part1(input), 11687500);
     }
-    let mut total = 1;
-    for presses in setup.special.values().flatten() {
-        total = lcm(total, *presses);
+
+    #[test]
+    fn network_counts_and_inputs() {
+        let input = "broadcaster -> a\n%a -> inv, con\n&inv -> b\n%b -> con\n&con -> output";
+        let mut network = Network::parse(input);
+        let counts = network.count_pulses(1);
+        assert_eq!((counts.low, counts.high), (4, 4));
+        assert_eq!(network.presses, 1);
+        let mut inputs = network.inputs("con");
+        inputs.sort_unstable();
+        assert_eq!(inputs, ["a", "b"]);
+        assert_eq!(network.inputs("a"), ["broadcaster"]);
+    }
+
+    #[test]
+    fn broadcaster_feeds_conjunctions() {
+        let mut network = Network::parse("broadcaster -> con\n&con -> out");
+        let mut sent = Vec::new();
+        network.press(|signal| sent.push((signal.from, signal.to, signal.pulse.is_high())));
+        assert_eq!(
+            sent,
+            [
+                ("button", "broadcaster", false),
+                ("broadcaster", "con", false),
+                ("con", "out", true)
+            ]
+        );
+    }
+
+    #[test]
+    fn presses_until_low_on_counters() {
+        let input = "broadcaster -> one, a\n%a -> b, two\n%b -> four\n&one -> last\n&two -> last\n&four -> last\n&last -> rx";
+        let mut network = Network::parse(input);
+        assert_eq!(presses_until_low(&mut network, "rx").unwrap(), 4);
+
+        let input = "broadcaster -> a\n%a -> inv\n&inv -> odd\n&odd -> last\n&last -> rx";
+        let error = presses_until_low(&mut Network::parse(input), "rx").unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "odd sent high pulses after 1 and 3 presses, not a clean cycle"
+        );
+    }
+
+    #[test]
+    fn structure_errors() {
+        let input = "broadcaster -> a\n%a -> inv, con\n&inv -> b\n%b -> con\n&con -> output";
+        let error = |target| {
+            presses_until_low(&mut Network::parse(input), target)
+                .unwrap_err()
+                .to_string()
+        };
+        assert_eq!(error("rx"), "rx has 0 inputs, expected exactly one");
+        assert_eq!(error("b"), "a is not a conjunction");
+        let error = presses_until_low(
+            &mut Network::parse("broadcaster -> a, b\n%a -> rx\n%b -> rx"),
+            "rx",
+        )
+        .unwrap_err();
+        assert_eq!(error.to_string(), "rx has 2 inputs, expected exactly one");
+        let error =
+            presses_until_low(&mut Network::parse("broadcaster -> a\n%a -> rx"), "rx").unwrap_err();
+        assert_eq!(error.to_string(), "a is not a conjunction");
+        let error = presses_until_low(
+            &mut Network::parse("broadcaster -> a\n%a -> b\n&last -> rx"),
+            "rx",
+        )
+        .unwrap_err();
+        assert_eq!(error.to_string(), "last has 0 inputs, expected exactly one");
     }
-    total
 }
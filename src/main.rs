@@ -25,13 +25,26 @@ mod day24;
 mod day25;
 mod shared;
 
+fn debug_mode(day: &str) -> Option<String> {
+    let days = std::env::var("AOC_DEBUG").ok()?;
+    days.split(',').find_map(|entry| {
+        let (name, mode) = entry.split_once(':').unwrap_or((entry, ""));
+        (name == day).then(|| mode.to_string())
+    })
+}
+
 macro_rules! run {
-    ($day:ident) => {{
+    ($day:ident $(, $debug:ident)?) => {{
         println!("=============== {} ===============", stringify!($day));
         let start = std::time::Instant::now();
         let input = std::fs::read_to_string(concat!("input/", stringify!($day), ".txt")).unwrap();
         println!("Part 1: {}", $day::part1(&input));
         println!("Part 2: {}", $day::part2(&input));
+        $(
+            if let Some(mode) = debug_mode(stringify!($day)) {
+                print!("{}", $day::$debug(&input, &mode));
+            }
+        )?
         println!("Elapsed: {:?}\n", start.elapsed());
     }};
 }
@@ -43,19 +56,19 @@ fn main() {
     run!(day04);
     run!(day05);
     run!(day06);
-    run!(day07);
+    run!(day07, debug);
     run!(day08);
     run!(day09);
-    run!(day10);
+    run!(day10, debug);
     run!(day11);
     run!(day12);
     run!(day13);
-    run!(day14);
-    run!(day15);
-    run!(day16);
-    run!(day17);
-    run!(day18);
-    run!(day19);
+    run!(day14, debug);
+    run!(day15, debug);
+    run!(day16, debug);
+    run!(day17, debug);
+    run!(day18, debug);
+    run!(day19, debug);
     run!(day20);
     run!(day21);
     run!(day22);
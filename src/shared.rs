@@ -1,3 +1,5 @@
+use std::ops::Range;
+
 fn gcd(a: u64, b: u64) -> u64 {
     if a == 0 {
         b
@@ -13,3 +15,190 @@ fn gcd(a: u64, b: u64) -> u64 {
 pub fn lcm(a: u64, b: u64) -> u64 {
     a / gcd(a, b) * b
 }
+
+fn extended_gcd(a: i128, b: i128) -> (i128, i128, i128) {
+    if b == 0 {
+        (a, 1, 0)
+    } else {
+        let (g, x, y) = extended_gcd(b, a % b);
+        (g, y, x - a / b * y)
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct Overflow;
+
+impl std::fmt::Display for Overflow {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "result does not fit in u64")
+    }
+}
+
+pub fn crt((a1, m1): (u64, u64), (a2, m2): (u64, u64)) -> Result<Option<(u64, u64)>, Overflow> {
+    let (g, x, _) = extended_gcd(m1 as i128, m2 as i128);
+    let diff = a2 as i128 - a1 as i128;
+    if diff % g != 0 {
+        return Ok(None);
+    }
+    let step = m2 as i128 / g;
+    let diff = (diff / g).rem_euclid(step) as u128;
+    let x = x.rem_euclid(step) as u128;
+    let k = diff * x % step as u128;
+    let modulus = m1 as u128 * step as u128;
+    let residue = (a1 % m1) as u128 + m1 as u128 * k;
+    Ok(Some((
+        residue.try_into().map_err(|_| Overflow)?,
+        modulus.try_into().map_err(|_| Overflow)?,
+    )))
+}
+
+fn word_mask(range: &Range<usize>, word: usize) -> u64 {
+    let low = range.start.max(word * 64).saturating_sub(word * 64);
+    let high = range.end.min(word * 64 + 64).saturating_sub(word * 64);
+    if low >= high {
+        0
+    } else {
+        (u64::MAX >> (64 - (high - low))) << low
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct BitSet(Vec<u64>);
+
+impl BitSet {
+    pub fn new(len: usize) -> Self {
+        BitSet(vec![0; len.div_ceil(64)])
+    }
+
+    pub fn insert(&mut self, index: usize) {
+        self.0[index / 64] |= 1 << (index % 64);
+    }
+
+    pub fn contains(&self, index: usize) -> bool {
+        self.0[index / 64] & (1 << (index % 64)) != 0
+    }
+
+    pub fn toggle(&mut self, index: usize) {
+        self.0[index / 64] ^= 1 << (index % 64);
+    }
+
+    pub fn ones(&self) -> impl Iterator<Item = usize> + '_ {
+        self.0.iter().enumerate().flat_map(|(word, &bits)| {
+            let mut bits = bits;
+            std::iter::from_fn(move || {
+                let bit = (bits != 0).then(|| bits.trailing_zeros() as usize)?;
+                bits &= bits - 1;
+                Some(word * 64 + bit)
+            })
+        })
+    }
+
+    fn words(range: &Range<usize>) -> Range<usize> {
+        range.start / 64..range.end.div_ceil(64)
+    }
+
+    pub fn count_range(&self, range: Range<usize>) -> usize {
+        Self::words(&range)
+            .map(|word| (self.0[word] & word_mask(&range, word)).count_ones() as usize)
+            .sum()
+    }
+
+    pub fn set_range(
+        &mut self,
+        range: Range<usize>,
+        ones: Range<usize>,
+        mut flipped: impl FnMut(usize),
+    ) {
+        for word in Self::words(&range) {
+            let segment = word_mask(&range, word);
+            let filled = word_mask(&ones, word);
+            let mut changed = (self.0[word] & segment) ^ filled;
+            self.0[word] = (self.0[word] & !segment) | filled;
+            while changed != 0 {
+                flipped(word * 64 + changed.trailing_zeros() as usize);
+                changed &= changed - 1;
+            }
+        }
+    }
+
+    pub fn union_with(&mut self, other: &Self) {
+        for (a, b) in self.0.iter_mut().zip(&other.0) {
+            *a |= b;
+        }
+    }
+
+    pub fn count_ones(&self) -> usize {
+        self.0.iter().map(|word| word.count_ones() as usize).sum()
+    }
+
+    pub fn hamming_distance(&self, other: &Self) -> u32 {
+        self.0
+            .iter()
+            .zip(&other.0)
+            .map(|(a, b)| (a ^ b).count_ones())
+            .sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn crt_combines_compatible_congruences() {
+        assert_eq!(crt((2, 3), (3, 5)), Ok(Some((8, 15))));
+        assert_eq!(crt((1, 4), (3, 6)), Ok(Some((9, 12))));
+        assert_eq!(crt((0, 1), (4, 7)), Ok(Some((4, 7))));
+    }
+
+    #[test]
+    fn crt_rejects_incompatible_congruences() {
+        assert_eq!(crt((0, 4), (1, 6)), Ok(None));
+    }
+
+    #[test]
+    fn crt_reports_overflow() {
+        let big = (1 << 40) + 1;
+        assert_eq!(crt((1, big), (2, big + 2)), Err(Overflow));
+    }
+
+    #[test]
+    fn crt_handles_large_moduli() {
+        let (m1, m2) = (u32::MAX as u64, u32::MAX as u64 + 2);
+        let (residue, modulus) = crt((5, m1), (7, m2)).unwrap().unwrap();
+        assert_eq!(modulus, m1 * m2);
+        assert_eq!((residue % m1, residue % m2), (5, 7));
+    }
+
+    #[test]
+    fn bitset_hamming_distance() {
+        let (mut a, mut b) = (BitSet::new(130), BitSet::new(130));
+        for i in [0, 63, 64, 129] {
+            a.insert(i);
+        }
+        for i in [63, 100, 129] {
+            b.insert(i);
+        }
+        assert_eq!(a.hamming_distance(&b), 3);
+        assert_eq!(a.hamming_distance(&a), 0);
+        a.union_with(&b);
+        assert_eq!(a.count_ones(), 5);
+    }
+
+    #[test]
+    fn bitset_ranges() {
+        let mut set = BitSet::new(200);
+        for i in [3, 60, 64, 70, 150] {
+            set.toggle(i);
+        }
+        assert!(set.contains(64) && !set.contains(65));
+        assert_eq!(set.count_range(4..151), 4);
+        assert_eq!(set.count_range(61..64), 0);
+
+        let mut flipped = Vec::new();
+        set.set_range(60..128, 60..63, |i| flipped.push(i));
+        flipped.sort_unstable();
+        assert_eq!(flipped, [61, 62, 64, 70]);
+        assert_eq!(set.ones().collect::<Vec<_>>(), [3, 60, 61, 62, 150]);
+    }
+}